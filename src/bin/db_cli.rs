@@ -2,10 +2,15 @@ use std::env;
 use std::fs;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use base_rust_web_api::db;
 
+/// `--no-wait`: fail at once when another run holds the migration lock
+/// instead of waiting up to `MIGRATION_LOCK_TIMEOUT_SECS`.
+static NO_WAIT: AtomicBool = AtomicBool::new(false);
+
 fn main() -> io::Result<()> {
     dotenv::dotenv().ok();
 
@@ -16,13 +21,28 @@ fn main() -> io::Result<()> {
     }
 
     let command = args.remove(0);
+    NO_WAIT.store(has_flag(&args, "--no-wait"), Ordering::Relaxed);
     match command.as_str() {
-        "migration:new" => create_sql_file("migrations"),
-        "seed:new" => create_sql_file("seeders"),
-        "migrate" => run_pending("migrations"),
-        "seed" => run_pending("seeders"),
-        "migrate:undo" => undo_last("migrations"),
-        "seed:undo" => undo_last("seeders"),
+        "migration:new" => create_sql_file("migrations", &args),
+        "seed:new" => create_sql_file("seeders", &args),
+        "migrate" => run_pending(
+            "migrations",
+            has_flag(&args, "--strict"),
+            has_flag(&args, "--dry-run"),
+        ),
+        "seed" => run_pending("seeders", false, has_flag(&args, "--dry-run")),
+        "migrate:to" => migrate_to(first_value(&args), has_flag(&args, "--strict")),
+        "migrate:undo" => undo(
+            "migrations",
+            first_value(&args),
+            has_flag(&args, "--dry-run"),
+        ),
+        "seed:undo" => undo("seeders", first_value(&args), has_flag(&args, "--dry-run")),
+        "migrate:redo" => redo(first_value(&args)),
+        "migrate:fresh" => fresh(has_flag(&args, "--yes")),
+        "migrate:status" => show_status("migrations"),
+        "migrate:check" => check_scripts(),
+        "seed:status" => show_status("seeders"),
         _ => {
             print_usage();
             Ok(())
@@ -33,21 +53,59 @@ fn main() -> io::Result<()> {
 fn print_usage() {
     eprintln!(
         "Usage:\n  \
-  cargo run --bin db_cli -- migration:new\n  \
-  cargo run --bin db_cli -- seed:new\n  \
-  cargo run --bin db_cli -- migrate\n  \
-  cargo run --bin db_cli -- seed\n  \
-  cargo run --bin db_cli -- migrate:undo\n  \
-  cargo run --bin db_cli -- seed:undo\n"
+  cargo run --bin db_cli -- migration:new [name]\n  \
+  cargo run --bin db_cli -- seed:new [name]\n  \
+  cargo run --bin db_cli -- migrate [--strict] [--dry-run]\n  \
+  cargo run --bin db_cli -- migrate:to <id> [--strict]\n  \
+  cargo run --bin db_cli -- seed [--dry-run]\n  \
+  cargo run --bin db_cli -- migrate:undo [count|id] [--dry-run]\n  \
+  cargo run --bin db_cli -- seed:undo [count|id] [--dry-run]\n  \
+  cargo run --bin db_cli -- migrate:redo [count]\n  \
+  cargo run --bin db_cli -- migrate:fresh [--yes]\n  \
+  cargo run --bin db_cli -- migrate:status\n  \
+  cargo run --bin db_cli -- migrate:check\n  \
+  cargo run --bin db_cli -- seed:status\n\n\
+Commands that change the database wait for a concurrent run to finish;\n\
+pass --no-wait to exit immediately instead.\n"
     );
 }
 
+fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|a| a == flag)
+}
+
+/// The first argument that isn't a `--flag`.
+fn first_value(args: &[String]) -> Option<&String> {
+    args.iter().find(|a| !a.starts_with("--"))
+}
+
+/// A `[count]` argument: a positive number, defaulting to 1.
+fn parse_count(arg: Option<&String>) -> io::Result<usize> {
+    match arg {
+        Some(value) => value
+            .parse::<usize>()
+            .ok()
+            .filter(|count| *count > 0)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid count '{}': expected a positive number", value),
+                )
+            }),
+        None => Ok(1),
+    }
+}
+
 fn prompt_name() -> io::Result<String> {
     print!("Enter name: ");
     io::stdout().flush()?;
     let mut name = String::new();
     io::stdin().read_line(&mut name)?;
-    Ok(name.trim().replace(' ', "_"))
+    Ok(sanitize_name(&name))
+}
+
+fn sanitize_name(name: &str) -> String {
+    name.trim().replace(' ', "_")
 }
 
 fn timestamp_ms() -> u128 {
@@ -57,16 +115,30 @@ fn timestamp_ms() -> u128 {
         .as_millis()
 }
 
-fn create_sql_file(kind: &str) -> io::Result<()> {
-    let name = prompt_name()?;
+fn scripts_dir(kind: &str) -> PathBuf {
+    let base = env::var("MIGRATIONS_DIR").unwrap_or_else(|_| "src/db".to_string());
+    PathBuf::from(base).join(kind)
+}
+
+fn create_sql_file(kind: &str, args: &[String]) -> io::Result<()> {
+    let name = if args.is_empty() {
+        prompt_name()?
+    } else {
+        sanitize_name(&args.join(" "))
+    };
     if name.is_empty() {
         eprintln!("Name cannot be empty.");
         return Ok(());
     }
 
-    let ts = timestamp_ms();
+    // Two calls in the same millisecond would otherwise share an id
+    let existing = script_ids(kind)?;
+    let mut ts = timestamp_ms();
+    while existing.contains(&ts.to_string()) {
+        ts += 1;
+    }
     let base = format!("{}_{}", ts, name);
-    let dir = PathBuf::from("src/db").join(kind);
+    let dir = scripts_dir(kind);
     fs::create_dir_all(&dir)?;
 
     let up_file = dir.join(format!("{}_up.sql", base));
@@ -102,115 +174,1033 @@ fn read_sql(path: &Path) -> io::Result<String> {
 }
 
 fn list_sql_files(kind: &str, suffix: &str) -> io::Result<Vec<PathBuf>> {
-    let dir = PathBuf::from("src/db").join(kind);
+    let dir = scripts_dir(kind);
     let mut files = Vec::new();
     if dir.exists() {
         for entry in fs::read_dir(dir)? {
             let entry = entry?;
             let path = entry.path();
-            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                if name.ends_with(suffix) {
-                    files.push(path);
-                }
+            if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                && name.ends_with(suffix)
+            {
+                files.push(path);
             }
         }
     }
-    files.sort();
+    files.sort_by_cached_key(|f| sort_key(f));
     Ok(files)
 }
 
+/// Orders scripts by their numeric id, so `99999_...` runs before
+/// `100000_...` even though it sorts after it as a string. Up and down files
+/// of one id stay next to each other; malformed names go last.
+fn sort_key(path: &Path) -> (bool, usize, String, String) {
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match parse_id_name_from_file(path) {
+        Some((id, _)) => {
+            // Compared as digit strings (length first) rather than parsed,
+            // so no id is too long to order
+            let digits = id.trim_start_matches('0').to_string();
+            (false, digits.len(), digits, filename)
+        }
+        None => (true, 0, String::new(), filename),
+    }
+}
+
+/// Splits `<timestamp>_<name>_up.sql` / `_down.sql` into id and name, or
+/// `None` if the file doesn't follow that format.
 fn parse_id_name_from_file(path: &Path) -> Option<(String, String)> {
-    let filename = path.file_name()?.to_string_lossy();
-    let parts: Vec<&str> = filename.split('_').collect();
-    if parts.len() < 2 {
+    let filename = path.file_name()?.to_str()?;
+    let stem = filename
+        .strip_suffix("_up.sql")
+        .or_else(|| filename.strip_suffix("_down.sql"))?;
+    let (id, name) = stem.split_once('_')?;
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) || name.is_empty() {
         return None;
     }
-    let id = parts[0].to_string();
-    let name = parts[1..]
-        .join("_")
-        .replace("_up.sql", "")
-        .replace("_down.sql", "");
-    Some((id, name))
+    Some((id.to_string(), name.to_string()))
+}
+
+/// Files in the scripts directory that would otherwise be skipped silently:
+/// `.sql` files with a malformed name, and `_up.sql` / `_down.sql` files
+/// missing their counterpart.
+fn script_problems(kind: &str) -> io::Result<Vec<String>> {
+    let mut problems = Vec::new();
+    let files = list_sql_files(kind, ".sql")?;
+    for file in &files {
+        if parse_id_name_from_file(file).is_none() {
+            problems.push(format!(
+                "{}: expected <timestamp>_<name>_up.sql or _down.sql",
+                file.display()
+            ));
+            continue;
+        }
+        let filename = file.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let counterpart = match filename.strip_suffix("_up.sql") {
+            Some(base) => format!("{}_down.sql", base),
+            None => format!("{}_up.sql", filename.trim_end_matches("_down.sql")),
+        };
+        if !file.with_file_name(&counterpart).exists() {
+            problems.push(format!("{}: missing {}", file.display(), counterpart));
+        }
+    }
+    let up_files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|f| f.to_string_lossy().ends_with("_up.sql"))
+        .collect();
+    for (id, names) in duplicate_ids(&up_files) {
+        problems.push(format!("id {} is shared by {}", id, names.join(", ")));
+    }
+    if kind == "seeders"
+        && let Err(problem) = dependency_order(&up_files)
+    {
+        problems.push(problem);
+    }
+    Ok(problems)
+}
+
+/// Groups of `_up.sql` files that share an id; only the first of each group
+/// would ever be applied.
+fn duplicate_ids(files: &[PathBuf]) -> Vec<(String, Vec<String>)> {
+    let mut by_id: Vec<(String, Vec<String>)> = Vec::new();
+    for file in files {
+        let Some((id, _)) = parse_id_name_from_file(file) else {
+            continue;
+        };
+        let name = file.display().to_string();
+        match by_id.iter_mut().find(|(existing, _)| *existing == id) {
+            Some((_, names)) => names.push(name),
+            None => by_id.push((id, vec![name])),
+        }
+    }
+    by_id.retain(|(_, names)| names.len() > 1);
+    by_id
+}
+
+fn ensure_unique_ids(files: &[PathBuf]) -> io::Result<()> {
+    let duplicates = duplicate_ids(files);
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    for (id, names) in &duplicates {
+        eprintln!("Id {} is used by more than one file:", id);
+        for name in names {
+            eprintln!("  {}", name);
+        }
+    }
+    Err(io::Error::other(
+        "duplicate script ids (rename one of the files to a new timestamp)",
+    ))
+}
+
+/// Ids listed in `-- depends: <id>[, <id>...]` lines of the comment block
+/// at the top of a seeder's `_up.sql`; the block ends at the first line of
+/// SQL.
+fn parse_depends(sql: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    for line in sql.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        let Some(comment) = line.strip_prefix("--") else {
+            break;
+        };
+        let comment = comment.trim();
+        let directive = comment
+            .split_once(':')
+            .filter(|(key, _)| key.trim().eq_ignore_ascii_case("depends"));
+        if let Some((_, list)) = directive {
+            ids.extend(
+                list.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_string),
+            );
+        }
+    }
+    ids
+}
+
+/// Orders `_up.sql` files so every seeder runs after the ones it depends
+/// on, keeping filename (timestamp) order otherwise. Returns indices into
+/// `files`, or a description of the unknown id or cycle that prevents it.
+fn dependency_order(files: &[PathBuf]) -> Result<Vec<usize>, String> {
+    let ids: Vec<String> = files
+        .iter()
+        .map(|f| {
+            parse_id_name_from_file(f)
+                .map(|(id, _)| id)
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut depends: Vec<Vec<usize>> = Vec::with_capacity(files.len());
+    for (file, id) in files.iter().zip(&ids) {
+        let sql = read_sql(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+        let mut deps = Vec::new();
+        for dep in parse_depends(&sql) {
+            let Some(index) = ids.iter().position(|other| *other == dep) else {
+                return Err(format!(
+                    "{}: depends on {}, which has no _up.sql file",
+                    file.display(),
+                    dep
+                ));
+            };
+            if dep == *id {
+                return Err(format!("{}: depends on itself", file.display()));
+            }
+            deps.push(index);
+        }
+        depends.push(deps);
+    }
+
+    // Kahn's algorithm, always taking the earliest ready file so that
+    // scripts without directives keep their timestamp order
+    let mut order = Vec::with_capacity(files.len());
+    let mut done = vec![false; files.len()];
+    while order.len() < files.len() {
+        let ready = (0..files.len()).find(|&i| !done[i] && depends[i].iter().all(|&dep| done[dep]));
+        let Some(next) = ready else {
+            return Err(format!(
+                "dependency cycle: {}",
+                describe_cycle(&depends, &done, &ids)
+            ));
+        };
+        done[next] = true;
+        order.push(next);
+    }
+    Ok(order)
+}
+
+/// Every unfinished script waits on another unfinished one, so following
+/// those edges from any of them must loop; returns the loop as `a -> b -> a`.
+fn describe_cycle(depends: &[Vec<usize>], done: &[bool], ids: &[String]) -> String {
+    let Some(start) = (0..done.len()).find(|&i| !done[i]) else {
+        return String::new();
+    };
+    let mut path = vec![start];
+    let mut current = start;
+    loop {
+        let Some(&next) = depends[current].iter().find(|&&dep| !done[dep]) else {
+            return String::new();
+        };
+        if let Some(pos) = path.iter().position(|&i| i == next) {
+            let mut cycle: Vec<&str> = path[pos..].iter().map(|&i| ids[i].as_str()).collect();
+            cycle.push(ids[next].as_str());
+            return cycle.join(" -> ");
+        }
+        path.push(next);
+        current = next;
+    }
+}
+
+/// `files` in the order they should run: seeders honour their `-- depends:`
+/// directives, everything else keeps filename order. Works for `_down.sql`
+/// lists too, taking the directives from the matching `_up.sql`.
+fn run_order(kind: &str, files: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    if kind != "seeders" {
+        return Ok(files);
+    }
+    let up_files = list_sql_files(kind, "_up.sql")?;
+    let order = dependency_order(&up_files).map_err(io::Error::other)?;
+    let ranked: Vec<String> = order
+        .into_iter()
+        .filter_map(|i| parse_id_name_from_file(&up_files[i]).map(|(id, _)| id))
+        .collect();
+
+    let mut files = files;
+    files.sort_by_key(|file| {
+        parse_id_name_from_file(file)
+            .and_then(|(id, _)| ranked.iter().position(|r| *r == id))
+            .unwrap_or(usize::MAX)
+    });
+    Ok(files)
 }
 
-fn run_pending(kind: &str) -> io::Result<()> {
+fn warn_script_problems(kind: &str) -> io::Result<()> {
+    for problem in script_problems(kind)? {
+        eprintln!("Warning: {}", problem);
+    }
+    Ok(())
+}
+
+/// `migrate:check`: reports malformed or unpaired files for migrations and
+/// seeders, exiting non-zero if there are any.
+fn check_scripts() -> io::Result<()> {
+    let mut found = false;
+    for kind in ["migrations", "seeders"] {
+        let problems = script_problems(kind)?;
+        if problems.is_empty() {
+            println!("{}: ok", kind);
+        }
+        for problem in problems {
+            found = true;
+            println!("{}: {}", kind, problem);
+        }
+    }
+    if found {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_pending(kind: &str, strict: bool, dry_run: bool) -> io::Result<()> {
+    warn_script_problems(kind)?;
     let runtime = tokio::runtime::Runtime::new().unwrap();
     runtime.block_on(async move {
         db::init_pool().await.map_err(to_io_err)?;
+        if dry_run {
+            // Read-only: no tracking tables created, no lock taken
+            return preview_pending(kind, strict).await;
+        }
         db::ensure_migrations_tables().await.map_err(to_io_err)?;
 
-        let applied = if kind == "migrations" {
-            db::applied_migration_ids().await.map_err(to_io_err)?
-        } else {
-            db::applied_seed_ids().await.map_err(to_io_err)?
+        let lock = lock_migrations().await?;
+        let result = apply_pending(kind, strict, None).await;
+        lock.release().await.map_err(to_io_err)?;
+        result
+    })
+}
+
+/// `migrate:to <id>`: brings the schema to exactly that migration, applying
+/// pending ones up to and including it, or reverting (newest first) the
+/// applied ones after it.
+fn migrate_to(target: Option<&String>, strict: bool) -> io::Result<()> {
+    let Some(target) = target else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "migrate:to needs a migration id, e.g. migrate:to 1712345678901",
+        ));
+    };
+    let ids: Vec<String> = list_sql_files("migrations", "_up.sql")?
+        .iter()
+        .filter_map(|f| parse_id_name_from_file(f))
+        .map(|(id, _)| id)
+        .collect();
+    let Some(position) = ids.iter().position(|id| id == target) else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No migration with id {}", target),
+        ));
+    };
+
+    warn_script_problems("migrations")?;
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async move {
+        db::init_pool().await.map_err(to_io_err)?;
+        db::ensure_migrations_tables().await.map_err(to_io_err)?;
+
+        let lock = lock_migrations().await?;
+        let result = step_to(&ids, position, strict).await;
+        lock.release().await.map_err(to_io_err)?;
+        result
+    })
+}
+
+async fn step_to(ids: &[String], position: usize, strict: bool) -> io::Result<()> {
+    let target = ids[position].as_str();
+    let applied = applied_ids("migrations").await.map_err(to_io_err)?;
+
+    let ahead: Vec<&String> = ids[position + 1..]
+        .iter()
+        .filter(|id| applied.contains(id))
+        .collect();
+    if !ahead.is_empty() {
+        // revert_applied skips scripts without a down file, so a gap would
+        // make it reach past the target; refuse up front instead
+        let down_ids = script_ids_with_suffix("migrations", "_down.sql")?;
+        if let Some(id) = ahead.iter().find(|id| !down_ids.contains(id)) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "migration {} has no _down.sql file; can't roll back to {}",
+                    id, target
+                ),
+            ));
+        }
+        revert_applied("migrations", ahead.len()).await?;
+    }
+
+    let behind = ids[..=position].iter().any(|id| !applied.contains(id));
+    if behind {
+        apply_pending("migrations", strict, Some(target)).await?;
+    }
+
+    if ahead.is_empty() && !behind {
+        println!("Already at migration {}.", target);
+    }
+    Ok(())
+}
+
+async fn applied_ids(kind: &str) -> Result<Vec<String>, sqlx::Error> {
+    if kind == "migrations" {
+        db::applied_migration_ids().await
+    } else {
+        db::applied_seed_ids().await
+    }
+}
+
+/// `applied_ids`, or none when the tracking tables don't exist yet, for the
+/// read-only paths that don't create them.
+async fn applied_ids_if_tracked(kind: &str) -> io::Result<Vec<String>> {
+    match applied_ids(kind).await {
+        Ok(ids) => Ok(ids),
+        // 42P01 = undefined_table: nothing has been applied yet
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => Ok(Vec::new()),
+        Err(e) => Err(to_io_err(e)),
+    }
+}
+
+/// Prints each pending script and its SQL without running anything.
+async fn preview_pending(kind: &str, strict: bool) -> io::Result<()> {
+    let applied = applied_ids_if_tracked(kind).await?;
+
+    let files = list_sql_files(kind, "_up.sql")?;
+    ensure_unique_ids(&files)?;
+    if kind == "migrations" && !applied.is_empty() {
+        verify_checksums(&files, strict).await?;
+    }
+    let files = run_order(kind, files)?;
+
+    let mut pending = 0;
+    for file in files {
+        let (id, _) = match parse_id_name_from_file(&file) {
+            Some(v) => v,
+            None => continue,
+        };
+        if applied.contains(&id) {
+            continue;
+        }
+        pending += 1;
+        println!(
+            "-- Would apply {}: {}",
+            kind.trim_end_matches('s'),
+            file.display()
+        );
+        println!("{}\n", read_sql(&file)?.trim_end());
+    }
+    if pending == 0 {
+        println!("No pending {}.", kind);
+    }
+    Ok(())
+}
+
+/// Applies pending scripts in order; with `until`, stops after the script
+/// with that id.
+async fn apply_pending(kind: &str, strict: bool, until: Option<&str>) -> io::Result<()> {
+    let applied = applied_ids(kind).await.map_err(to_io_err)?;
+
+    let files = list_sql_files(kind, "_up.sql")?;
+    ensure_unique_ids(&files)?;
+
+    if kind == "migrations" {
+        verify_checksums(&files, strict).await?;
+    }
+    let mut files = run_order(kind, files)?;
+    if let Some(until) = until
+        && let Some(end) = files
+            .iter()
+            .position(|f| parse_id_name_from_file(f).is_some_and(|(id, _)| id == until))
+    {
+        files.truncate(end + 1);
+    }
+
+    for file in files {
+        let (id, name) = match parse_id_name_from_file(&file) {
+            Some(v) => v,
+            None => continue,
         };
+        if applied.contains(&id) {
+            continue;
+        }
+        let sql = read_sql(&file)?;
+        let elapsed_ms = apply_script(kind, id, name, sql).await.map_err(|e| {
+            io::Error::other(format!(
+                "{}: {} (rolled back, nothing recorded)",
+                file.display(),
+                e
+            ))
+        })?;
+        println!(
+            "Applied {}: {} ({}ms)",
+            kind.trim_end_matches('s'),
+            file.display(),
+            elapsed_ms
+        );
+    }
+    Ok(())
+}
 
-        let files = list_sql_files(kind, "_up.sql")?;
-        for file in files {
-            let (id, name) = match parse_id_name_from_file(&file) {
-                Some(v) => v,
-                None => continue,
-            };
-            if applied.contains(&id) {
-                continue;
-            }
-            let sql = read_sql(&file)?;
-            db::execute_sql(&sql).await.map_err(to_io_err)?;
-            if kind == "migrations" {
-                db::mark_migration_applied(&id, &name)
-                    .await
-                    .map_err(to_io_err)?;
+/// Runs one `_up.sql` and records it in the same transaction, so a script
+/// that fails (or a tracking insert that fails) leaves neither the schema
+/// change nor the row behind. Returns how long the script took.
+async fn apply_script(
+    kind: &str,
+    id: String,
+    name: String,
+    sql: String,
+) -> Result<i64, sqlx::Error> {
+    let is_migration = kind == "migrations";
+    db::with_transaction(move |tx| {
+        Box::pin(async move {
+            let started = Instant::now();
+            db::execute_sql_on(tx, &sql).await?;
+            let elapsed_ms = started.elapsed().as_millis() as i64;
+            let checksum = db::sql_checksum(&sql);
+            if is_migration {
+                db::mark_migration_applied(tx, &id, &name, &checksum, elapsed_ms).await?;
             } else {
-                db::mark_seed_applied(&id, &name).await.map_err(to_io_err)?;
+                db::mark_seed_applied(tx, &id, &name, &checksum, elapsed_ms).await?;
             }
-            println!("Applied {}: {}", kind.trim_end_matches('s'), file.display());
+            Ok(elapsed_ms)
+        })
+    })
+    .await
+}
+
+/// Warns about applied migrations whose `_up.sql` changed since they ran;
+/// with `strict` that's an error instead.
+async fn verify_checksums(files: &[PathBuf], strict: bool) -> io::Result<()> {
+    let recorded = db::applied_migration_checksums().await.map_err(to_io_err)?;
+
+    let mut changed = Vec::new();
+    for file in files {
+        let (id, _) = match parse_id_name_from_file(file) {
+            Some(v) => v,
+            None => continue,
+        };
+        if let Some(Some(checksum)) = recorded.get(&id)
+            && *checksum != db::sql_checksum(&read_sql(file)?)
+        {
+            changed.push(file.display().to_string());
         }
-        Ok(())
+    }
+
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!("WARNING: applied migrations were modified after being applied:");
+    for file in &changed {
+        eprintln!("  {}", file);
+    }
+    if strict {
+        return Err(io::Error::other("migration checksum mismatch"));
+    }
+    eprintln!("Edits to applied migrations are not re-run; pass --strict to make this an error.");
+    Ok(())
+}
+
+/// `undo [count]` reverts the most recent scripts; `undo <id>` reverts just
+/// that one, regardless of order. With `dry_run`, prints what would be
+/// reverted and the SQL instead.
+fn undo(kind: &str, arg: Option<&String>, dry_run: bool) -> io::Result<()> {
+    let file_ids = script_ids(kind)?;
+    let down_files = run_order(kind, list_sql_files(kind, "_down.sql")?)?;
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async move {
+        db::init_pool().await.map_err(to_io_err)?;
+        if dry_run {
+            // Read-only: no tracking tables created, no lock taken
+            let applied = applied_ids_if_tracked(kind).await?;
+            let files = plan_undo(kind, arg, &file_ids, down_files, &applied)?;
+            return preview_revert(kind, &files);
+        }
+        db::ensure_migrations_tables().await.map_err(to_io_err)?;
+
+        let lock = lock_migrations().await?;
+        let result = match applied_ids(kind).await {
+            Ok(applied) => match plan_undo(kind, arg, &file_ids, down_files, &applied) {
+                Ok(files) => revert_files(kind, files).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(to_io_err(e)),
+        };
+        lock.release().await.map_err(to_io_err)?;
+        result
+    })
+}
+
+/// Undo arguments of more digits than this are script ids (millisecond
+/// timestamps), never counts.
+const MAX_COUNT_DIGITS: usize = 4;
+
+/// The `_down.sql` files `undo` would run, in order, with their ids.
+/// `down_files` are all `_down.sql` files in run order.
+fn plan_undo(
+    kind: &str,
+    arg: Option<&String>,
+    file_ids: &[String],
+    down_files: Vec<PathBuf>,
+    applied: &[String],
+) -> io::Result<Vec<(String, PathBuf)>> {
+    // An id that is applied but whose files are gone still goes through
+    // down_file_for, which reports the missing down file. A long number is
+    // an id too, so a mistyped one fails instead of reverting everything
+    if let Some(id) = arg
+        && (file_ids.contains(id)
+            || applied.contains(id)
+            || (id.len() > MAX_COUNT_DIGITS && id.bytes().all(|b| b.is_ascii_digit())))
+    {
+        return Ok(vec![(
+            id.clone(),
+            down_file_for(kind, id, down_files, applied)?,
+        )]);
+    }
+    // Asking for more than are applied reverts all of them
+    Ok(newest_of(down_files, parse_count(arg)?, applied))
+}
+
+fn script_ids(kind: &str) -> io::Result<Vec<String>> {
+    script_ids_with_suffix(kind, ".sql")
+}
+
+fn script_ids_with_suffix(kind: &str, suffix: &str) -> io::Result<Vec<String>> {
+    Ok(list_sql_files(kind, suffix)?
+        .iter()
+        .filter_map(|f| parse_id_name_from_file(f))
+        .map(|(id, _)| id)
+        .collect())
+}
+
+fn down_file_for(
+    kind: &str,
+    id: &str,
+    down_files: Vec<PathBuf>,
+    applied: &[String],
+) -> io::Result<PathBuf> {
+    let label = kind.trim_end_matches('s');
+    if !applied.iter().any(|a| a == id) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} {} is not applied", label, id),
+        ));
+    }
+    down_files
+        .into_iter()
+        .find(|f| parse_id_name_from_file(f).is_some_and(|(file_id, _)| file_id == id))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} {} has no _down.sql file", label, id),
+            )
+        })
+}
+
+/// Down files of the `count` most recently ordered applied scripts, newest
+/// first.
+fn newest_applied(
+    kind: &str,
+    count: usize,
+    applied: &[String],
+) -> io::Result<Vec<(String, PathBuf)>> {
+    // Dependents are reverted before the seeders they depend on
+    let down_files = run_order(kind, list_sql_files(kind, "_down.sql")?)?;
+    Ok(newest_of(down_files, count, applied))
+}
+
+/// The last `count` of `down_files` (in run order) whose scripts are
+/// applied, newest first, with their ids.
+fn newest_of(
+    mut down_files: Vec<PathBuf>,
+    count: usize,
+    applied: &[String],
+) -> Vec<(String, PathBuf)> {
+    down_files.reverse();
+    down_files
+        .into_iter()
+        .filter_map(|file| parse_id_name_from_file(&file).map(|(id, _)| (id, file)))
+        .filter(|(id, _)| applied.contains(id))
+        .take(count)
+        .collect()
+}
+
+async fn revert_applied(kind: &str, count: usize) -> io::Result<()> {
+    let applied = applied_ids(kind).await.map_err(to_io_err)?;
+    revert_files(kind, newest_applied(kind, count, &applied)?).await
+}
+
+/// Runs the down files and removes their tracking rows, all in one
+/// transaction so a mid-rollback failure leaves the schema and the tracking
+/// table untouched.
+async fn revert_files(kind: &str, files: Vec<(String, PathBuf)>) -> io::Result<()> {
+    let label = kind.trim_end_matches('s');
+    if files.is_empty() {
+        println!("No {} to undo.", kind);
+        return Ok(());
+    }
+    let mut scripts = Vec::with_capacity(files.len());
+    for (id, file) in &files {
+        scripts.push((id.clone(), read_sql(file)?));
+    }
+
+    let is_migration = kind == "migrations";
+    db::with_transaction(move |tx| {
+        Box::pin(async move {
+            for (id, sql) in &scripts {
+                db::execute_sql_on(tx, sql).await?;
+                if is_migration {
+                    db::unmark_migration_applied(tx, id).await?;
+                } else {
+                    db::unmark_seed_applied(tx, id).await?;
+                }
+            }
+            Ok::<_, sqlx::Error>(())
+        })
     })
+    .await
+    .map_err(to_io_err)?;
+
+    for (_, file) in files {
+        println!("Reverted {}: {}", label, file.display());
+    }
+    Ok(())
+}
+
+fn preview_revert(kind: &str, files: &[(String, PathBuf)]) -> io::Result<()> {
+    if files.is_empty() {
+        println!("No {} to undo.", kind);
+    }
+    for (_, file) in files {
+        println!(
+            "-- Would revert {}: {}",
+            kind.trim_end_matches('s'),
+            file.display()
+        );
+        println!("{}\n", read_sql(file)?.trim_end());
+    }
+    Ok(())
 }
 
-fn undo_last(kind: &str) -> io::Result<()> {
+/// `migrate:redo [count]`: reverts the last `count` migrations, then
+/// re-applies them, each in its own transaction. Handy while iterating on a
+/// migration; the re-applied files' checksums are recorded afresh.
+fn redo(arg: Option<&String>) -> io::Result<()> {
+    let count = parse_count(arg)?;
     let runtime = tokio::runtime::Runtime::new().unwrap();
     runtime.block_on(async move {
         db::init_pool().await.map_err(to_io_err)?;
         db::ensure_migrations_tables().await.map_err(to_io_err)?;
 
+        let lock = lock_migrations().await?;
+        let result = redo_newest(count).await;
+        lock.release().await.map_err(to_io_err)?;
+        result
+    })
+}
+
+async fn redo_newest(count: usize) -> io::Result<()> {
+    let applied = applied_ids("migrations").await.map_err(to_io_err)?;
+    let files = newest_applied("migrations", count, &applied)?;
+    // newest_applied skips scripts without a down file, which would make
+    // redo reach past the ones asked for
+    let expected = script_ids_with_suffix("migrations", "_up.sql")?
+        .into_iter()
+        .filter(|id| applied.contains(id))
+        .rev()
+        .take(count);
+    for id in expected {
+        if !files.iter().any(|(file_id, _)| *file_id == id) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("migration {} has no _down.sql file", id),
+            ));
+        }
+    }
+
+    let mut up_files = Vec::with_capacity(files.len());
+    for (id, down_file) in &files {
+        let up_file = down_file.with_file_name(
+            down_file
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default()
+                .replace("_down.sql", "_up.sql"),
+        );
+        if !up_file.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("migration {} has no _up.sql file", id),
+            ));
+        }
+        up_files.push(up_file);
+    }
+
+    for (id, down_file) in files {
+        revert_files("migrations", vec![(id, down_file)]).await?;
+    }
+
+    // Oldest first, the order they were applied in
+    for up_file in up_files.into_iter().rev() {
+        let Some((id, name)) = parse_id_name_from_file(&up_file) else {
+            continue;
+        };
+        let sql = read_sql(&up_file)?;
+        let elapsed_ms = apply_script("migrations", id, name, sql)
+            .await
+            .map_err(|e| {
+                io::Error::other(format!(
+                    "{}: {} (rolled back; its down migration stays committed, so it is now pending)",
+                    up_file.display(),
+                    e
+                ))
+            })?;
+        println!(
+            "Applied migration: {} ({}ms)",
+            up_file.display(),
+            elapsed_ms
+        );
+    }
+    Ok(())
+}
+
+fn fresh(assume_yes: bool) -> io::Result<()> {
+    match env::var("APP_ENV") {
+        Ok(app_env) if !app_env.is_empty() && app_env != "production" => {}
+        _ => {
+            eprintln!(
+                "migrate:fresh requires APP_ENV to be set to something other than production."
+            );
+            std::process::exit(1);
+        }
+    }
+
+    if !assume_yes {
+        print!("This will drop every table in the public schema. Continue? [y/N]: ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async move {
+        db::init_pool().await.map_err(to_io_err)?;
+        let lock = lock_migrations().await?;
+        let result = reset_schema().await;
+        lock.release().await.map_err(to_io_err)?;
+        result
+    })
+}
+
+async fn reset_schema() -> io::Result<()> {
+    db::execute_sql("DROP SCHEMA public CASCADE")
+        .await
+        .map_err(to_io_err)?;
+    db::execute_sql("CREATE SCHEMA public")
+        .await
+        .map_err(to_io_err)?;
+    println!("Dropped and recreated the public schema");
+
+    db::ensure_migrations_tables().await.map_err(to_io_err)?;
+    apply_pending("migrations", false, None).await
+}
+
+fn show_status(kind: &str) -> io::Result<()> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let missing = runtime.block_on(async move {
+        db::init_pool().await.map_err(to_io_err)?;
+        db::ensure_migrations_tables().await.map_err(to_io_err)?;
+
         let applied = if kind == "migrations" {
-            db::applied_migration_ids().await.map_err(to_io_err)?
+            db::applied_migrations().await.map_err(to_io_err)?
         } else {
-            db::applied_seed_ids().await.map_err(to_io_err)?
+            db::applied_seeds().await.map_err(to_io_err)?
         };
 
-        let mut files = list_sql_files(kind, "_down.sql")?;
-        files.sort();
-        files.reverse();
+        let files = list_sql_files(kind, "_up.sql")?;
+        let scripts: Vec<(String, String)> = files
+            .iter()
+            .filter_map(|f| parse_id_name_from_file(f))
+            .collect();
+        if scripts.is_empty() && applied.is_empty() {
+            println!("No {} found.", kind);
+            return Ok(0);
+        }
 
-        for file in files {
-            let (id, _) = match parse_id_name_from_file(&file) {
-                Some(v) => v,
-                None => continue,
-            };
-            if !applied.contains(&id) {
-                continue;
+        let mut rows = vec![[
+            "STATUS".to_string(),
+            "SCRIPT".to_string(),
+            "APPLIED AT".to_string(),
+            "TOOK".to_string(),
+        ]];
+        let applied_row = |status: &str, entry: &db::AppliedScript| {
+            [
+                status.to_string(),
+                format!("{}_{}", entry.id, entry.name),
+                entry.applied_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                entry
+                    .execution_time_ms
+                    .map(|ms| format!("{}ms", ms))
+                    .unwrap_or_default(),
+            ]
+        };
+        for (id, name) in &scripts {
+            match applied.iter().find(|a| a.id == *id) {
+                Some(entry) => rows.push(applied_row("APPLIED", entry)),
+                None => rows.push([
+                    "PENDING".to_string(),
+                    format!("{}_{}", id, name),
+                    String::new(),
+                    String::new(),
+                ]),
             }
-            let sql = read_sql(&file)?;
-            db::execute_sql(&sql).await.map_err(to_io_err)?;
-            if kind == "migrations" {
-                db::unmark_migration_applied(&id).await.map_err(to_io_err)?;
+        }
+        // Recorded as applied but no longer on disk, usually a file lost in
+        // a branch merge; it can't be undone or re-checked without it
+        let missing: Vec<&db::AppliedScript> = applied
+            .iter()
+            .filter(|a| !scripts.iter().any(|(id, _)| *id == a.id))
+            .collect();
+        for entry in &missing {
+            rows.push(applied_row("MISSING", entry));
+        }
+
+        print_table(&rows);
+        Ok::<_, io::Error>(missing.len())
+    })?;
+
+    if missing > 0 {
+        eprintln!(
+            "{} applied {} no longer on disk (MISSING above); restore the files before migrating.",
+            missing,
+            if missing == 1 {
+                format!("{} is", kind.trim_end_matches('s'))
             } else {
-                db::unmark_seed_applied(&id).await.map_err(to_io_err)?;
+                format!("{} are", kind)
             }
-            println!(
-                "Reverted {}: {}",
-                kind.trim_end_matches('s'),
-                file.display()
+        );
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Prints rows with each column padded to its widest cell.
+fn print_table<const N: usize>(rows: &[[String; N]]) {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+async fn lock_migrations() -> io::Result<db::AdvisoryLock> {
+    let no_wait = NO_WAIT.load(Ordering::Relaxed);
+    let wait_secs = if no_wait {
+        0
+    } else {
+        env::var("MIGRATION_LOCK_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(60)
+    };
+
+    match db::try_advisory_lock(db::MIGRATION_LOCK_KEY, Duration::from_secs(wait_secs))
+        .await
+        .map_err(to_io_err)?
+    {
+        Some(lock) => Ok(lock),
+        None if no_wait => {
+            eprintln!("Another migration run is in progress; exiting because --no-wait was given.");
+            std::process::exit(1);
+        }
+        None => {
+            eprintln!(
+                "Another migration run is in progress; gave up waiting for the lock after {}s.",
+                wait_secs
             );
-            break;
+            std::process::exit(1);
         }
-        Ok(())
-    })
+    }
 }
 
 fn to_io_err(err: sqlx::Error) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, err.to_string())
+    io::Error::other(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_sort_by_numeric_id() {
+        let mut files: Vec<PathBuf> = [
+            "100000_y_up.sql",
+            "99999_x_down.sql",
+            "not_a_migration.sql",
+            "99999_x_up.sql",
+            "0100001_z_up.sql",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        files.sort_by_cached_key(|f| sort_key(f));
+
+        let names: Vec<_> = files.iter().map(|f| f.to_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            [
+                "99999_x_down.sql",
+                "99999_x_up.sql",
+                "100000_y_up.sql",
+                "0100001_z_up.sql",
+                "not_a_migration.sql",
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_must_be_positive() {
+        let arg = |value: &str| Some(value.to_string());
+
+        assert_eq!(parse_count(None).unwrap(), 1);
+        assert_eq!(parse_count(arg("3").as_ref()).unwrap(), 3);
+        assert!(parse_count(arg("0").as_ref()).is_err());
+        assert!(parse_count(arg("-1").as_ref()).is_err());
+        assert!(parse_count(arg("two").as_ref()).is_err());
+    }
+
+    #[test]
+    fn undo_takes_counts_and_ids() {
+        let arg = |value: &str| Some(value.to_string());
+        let down_files: Vec<PathBuf> = ["1_a_down.sql", "2_b_down.sql", "1712345678901_c_down.sql"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let file_ids = ["1", "2", "1712345678901"].map(String::from);
+        let applied = ["1", "2"].map(String::from);
+        let plan = |value: &str| {
+            plan_undo(
+                "migrations",
+                arg(value).as_ref(),
+                &file_ids,
+                down_files.clone(),
+                &applied,
+            )
+            .map(|files| files.into_iter().map(|(id, _)| id).collect::<Vec<_>>())
+        };
+
+        // More than are applied stops at what is applied
+        assert_eq!(plan("5").unwrap(), ["2", "1"]);
+        assert_eq!(plan("1").unwrap(), ["1"]);
+        // An id that isn't applied, whether its files exist or not
+        assert!(plan("1712345678901").is_err());
+        assert!(plan("1799999999999").is_err());
+    }
 }
@@ -22,7 +22,7 @@ fn main() -> io::Result<()> {
 
     write_file_if_missing(
         entity_dir.join("mod.rs"),
-        &format!("pub mod controller;\npub mod dto;\npub mod repo;\npub mod service;\n"),
+        "pub mod controller;\npub mod dto;\npub mod repo;\npub mod service;\n",
     )?;
 
     write_file_if_missing(
@@ -51,6 +51,7 @@ fn main() -> io::Result<()> {
 
     let controller_template = r#"use std::collections::HashMap;
 
+use crate::primitives::http::method::Method;
 use crate::primitives::http::request::Request;
 use crate::primitives::http::response::Response;
 use crate::route;
@@ -64,11 +65,11 @@ pub struct {{ENTITY}}Controller;
 impl {{ENTITY}}Controller {
     pub fn routes() -> Vec<Route> {
         vec![
-            Route::new("GET", &["{{MODULE}}"], vec![route!({{ENTITY}}Controller::get_all)]),
-            Route::new("POST", &["{{MODULE}}"], vec![route!({{ENTITY}}Controller::create)]),
-            Route::new("GET", &["{{MODULE}}", ":id"], vec![route!({{ENTITY}}Controller::get_one)]),
-            Route::new("PUT", &["{{MODULE}}", ":id"], vec![route!({{ENTITY}}Controller::update)]),
-            Route::new("DELETE", &["{{MODULE}}", ":id"], vec![route!({{ENTITY}}Controller::delete)]),
+            Route::new(Method::Get, &["{{MODULE}}"], vec![route!({{ENTITY}}Controller::get_all)]),
+            Route::new(Method::Post, &["{{MODULE}}"], vec![route!({{ENTITY}}Controller::create)]),
+            Route::new(Method::Get, &["{{MODULE}}", ":id"], vec![route!({{ENTITY}}Controller::get_one)]),
+            Route::new(Method::Put, &["{{MODULE}}", ":id"], vec![route!({{ENTITY}}Controller::update)]),
+            Route::new(Method::Delete, &["{{MODULE}}", ":id"], vec![route!({{ENTITY}}Controller::delete)]),
         ]
     }
 
@@ -80,7 +81,7 @@ impl {{ENTITY}}Controller {
         Response {
             status_code: 200,
             headers,
-            body,
+            body: body.into(),
         }
     }
 
@@ -93,7 +94,7 @@ impl {{ENTITY}}Controller {
         Response {
             status_code: 200,
             headers,
-            body,
+            body: body.into(),
         }
     }
 
@@ -105,7 +106,7 @@ impl {{ENTITY}}Controller {
         Response {
             status_code: 201,
             headers,
-            body,
+            body: body.into(),
         }
     }
 
@@ -118,7 +119,7 @@ impl {{ENTITY}}Controller {
         Response {
             status_code: 200,
             headers,
-            body,
+            body: body.into(),
         }
     }
 
@@ -131,7 +132,7 @@ impl {{ENTITY}}Controller {
         Response {
             status_code: 200,
             headers,
-            body,
+            body: body.into(),
         }
     }
 }
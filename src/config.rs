@@ -0,0 +1,530 @@
+use sqlx::postgres::PgSslMode;
+use std::env;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Every setting read from the environment (and `.env`), parsed and validated
+/// once. Unset variables take their documented defaults; variables that are
+/// set but invalid are errors rather than silently falling back.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub db: DbConfig,
+    pub cors: CorsConfig,
+    pub rate_limit: RateLimitConfig,
+    pub compression: CompressionConfig,
+    /// `API_TOKENS`, accepted by `auth::require_bearer`.
+    pub api_tokens: Vec<String>,
+    /// `BCRYPT_COST`, or `None` for bcrypt's default.
+    pub bcrypt_cost: Option<u32>,
+    /// `IDEMPOTENCY_TTL_SECS`: how long `idempotency::idempotency` replays
+    /// a stored response.
+    pub idempotency_ttl: Duration,
+    /// `IDEMPOTENCY_MAX_ENTRIES`: keys `idempotency::idempotency` keeps at once.
+    pub idempotency_max_entries: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// `SERVER_HOST`, the address the TCP listener binds.
+    pub host: IpAddr,
+    /// `SERVER_PORT`, or `PORT` for backwards compatibility.
+    pub port: u16,
+    /// `SERVER_UDS`: listen on this Unix domain socket instead of TCP.
+    pub uds: Option<String>,
+    pub cores: usize,
+    /// Certificate and key paths; HTTPS is served when both are set.
+    pub tls: Option<(String, String)>,
+    pub keep_alive_timeout: Duration,
+    pub keep_alive_max_requests: usize,
+    pub request_read_timeout: Duration,
+    pub shutdown_timeout: Duration,
+    pub max_body_size: usize,
+    pub request_id_header: String,
+    pub trust_proxy: bool,
+    /// `TRUSTED_PROXIES`: our own proxies, skipped when reading
+    /// `X-Forwarded-For` from the right.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// `LOG_FORMAT=json`
+    pub log_json: bool,
+    /// Request headers masked in logs, lowercased: the credential headers
+    /// plus any in `LOG_REDACT_HEADERS`.
+    pub log_redact_headers: Vec<String>,
+    pub tracing: bool,
+    pub metrics_path: String,
+    pub metrics_port: Option<u16>,
+    pub static_dir: String,
+    pub static_max_age: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DbConfig {
+    /// `DATABASE_URL`, or one assembled from the `DB_*` parts.
+    pub url: String,
+    /// `DB_HOST`/`DB_NAME` as given, for the startup banner.
+    pub host: Option<String>,
+    pub name: Option<String>,
+    pub replica_url: Option<String>,
+    pub replica_host: Option<String>,
+    pub replica_port: Option<u16>,
+    pub ssl_mode: Option<PgSslMode>,
+    pub ssl_root_cert: Option<String>,
+    pub max_connections: u32,
+    pub min_connections: Option<u32>,
+    pub idle_timeout: Option<Duration>,
+    pub max_lifetime: Option<Duration>,
+    pub acquire_timeout: Duration,
+    pub connect_timeout: Duration,
+    pub connect_retries: u32,
+    pub connect_backoff: Duration,
+    pub max_per_page: i64,
+    pub slow_query: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// `CORS_ALLOWED_ORIGINS`; `None` disables CORS.
+    pub allowed_origins: Option<String>,
+    pub allowed_methods: String,
+    pub allowed_headers: String,
+    pub allow_credentials: bool,
+    pub max_age: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub requests: u32,
+    pub window: Duration,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    pub min_size: usize,
+    /// `COMPRESSION_ENCODINGS`, lowercased; empty disables compression.
+    pub encodings: Vec<String>,
+}
+
+/// Every problem found while loading, reported together.
+#[derive(Debug, Clone)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+static CONFIG: OnceLock<Result<Config, ConfigError>> = OnceLock::new();
+
+/// The loaded configuration, or every problem with it.
+pub fn try_get() -> Result<&'static Config, &'static ConfigError> {
+    CONFIG.get_or_init(Config::from_env).as_ref()
+}
+
+/// The loaded configuration. Panics if it is invalid; binaries call `init`
+/// first so that never happens mid-request.
+pub fn get() -> &'static Config {
+    match try_get() {
+        Ok(config) => config,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+/// Loads and validates the configuration, exiting with the full list of
+/// problems if anything is wrong.
+pub fn init() -> &'static Config {
+    match try_get() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads variables and collects every parse or validation error.
+struct Loader {
+    errors: Vec<String>,
+}
+
+impl Loader {
+    /// Trimmed value, treating empty as unset.
+    fn string(&self, key: &str) -> Option<String> {
+        env::var(key)
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    }
+
+    fn string_or(&self, key: &str, default: &str) -> String {
+        self.string(key).unwrap_or_else(|| default.to_string())
+    }
+
+    /// Comma-separated values, trimmed, with empty entries dropped.
+    fn list(&self, key: &str) -> Option<Vec<String>> {
+        self.string(key).map(|v| {
+            v.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+    }
+
+    fn parse_opt<T: FromStr>(&mut self, key: &str, expected: &str) -> Option<T> {
+        let value = self.string(key)?;
+        match value.parse::<T>() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.errors
+                    .push(format!("{}='{}' is not {}", key, value, expected));
+                None
+            }
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, key: &str, expected: &str, default: T) -> T {
+        self.parse_opt(key, expected).unwrap_or(default)
+    }
+
+    fn secs(&mut self, key: &str, default: u64) -> Duration {
+        Duration::from_secs(self.parse(key, "a number of seconds", default))
+    }
+
+    fn millis(&mut self, key: &str, default: u64) -> Duration {
+        Duration::from_millis(self.parse(key, "a number of milliseconds", default))
+    }
+
+    fn flag(&mut self, key: &str, default: bool) -> bool {
+        let Some(value) = self.string(key) else {
+            return default;
+        };
+        match value.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" => true,
+            "0" | "false" | "no" => false,
+            _ => {
+                self.errors
+                    .push(format!("{}='{}' is not true or false", key, value));
+                default
+            }
+        }
+    }
+
+    fn check(&mut self, ok: bool, problem: impl Into<String>) {
+        if !ok {
+            self.errors.push(problem.into());
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config, ConfigError> {
+        let mut env = Loader { errors: Vec::new() };
+
+        let server = ServerConfig::load(&mut env);
+        let db = DbConfig::load(&mut env);
+
+        let cors = CorsConfig {
+            allowed_origins: env.string("CORS_ALLOWED_ORIGINS"),
+            allowed_methods: env
+                .string_or("CORS_ALLOWED_METHODS", "GET,POST,PUT,PATCH,DELETE,OPTIONS"),
+            allowed_headers: env.string_or("CORS_ALLOWED_HEADERS", "Content-Type,Authorization"),
+            allow_credentials: env.flag("CORS_ALLOW_CREDENTIALS", false),
+            max_age: env.parse("CORS_MAX_AGE", "a number of seconds", 600),
+        };
+
+        let requests = env.parse("RATE_LIMIT_REQUESTS", "a number", 60u32);
+        let window = env.secs("RATE_LIMIT_WINDOW_SECS", 60);
+        let burst = env.parse("RATE_LIMIT_BURST", "a number", requests);
+        env.check(requests > 0, "RATE_LIMIT_REQUESTS must be greater than 0");
+        env.check(
+            !window.is_zero(),
+            "RATE_LIMIT_WINDOW_SECS must be greater than 0",
+        );
+        env.check(burst > 0, "RATE_LIMIT_BURST must be greater than 0");
+        let rate_limit = RateLimitConfig {
+            requests,
+            window,
+            burst,
+        };
+
+        // Unlike other lists, an empty COMPRESSION_ENCODINGS is meaningful
+        let encodings: Vec<String> = match env::var("COMPRESSION_ENCODINGS") {
+            Ok(value) => value
+                .split(',')
+                .map(|e| e.trim().to_ascii_lowercase())
+                .filter(|e| !e.is_empty())
+                .collect(),
+            Err(_) => vec!["gzip".to_string(), "deflate".to_string()],
+        };
+        for encoding in &encodings {
+            env.check(
+                matches!(encoding.as_str(), "gzip" | "x-gzip" | "deflate"),
+                format!(
+                    "COMPRESSION_ENCODINGS: unknown encoding '{}' (expected gzip or deflate)",
+                    encoding
+                ),
+            );
+        }
+        let compression = CompressionConfig {
+            min_size: env.parse("COMPRESSION_MIN_SIZE", "a size in bytes", 1024),
+            encodings,
+        };
+
+        let api_tokens = env.list("API_TOKENS").unwrap_or_default();
+
+        let bcrypt_cost = env.parse_opt::<u32>("BCRYPT_COST", "a number");
+        if let Some(cost) = bcrypt_cost {
+            env.check(
+                (4..=31).contains(&cost),
+                format!("BCRYPT_COST={} must be between 4 and 31", cost),
+            );
+        }
+
+        let idempotency_ttl = env.secs("IDEMPOTENCY_TTL_SECS", 86400);
+        env.check(
+            !idempotency_ttl.is_zero(),
+            "IDEMPOTENCY_TTL_SECS must be greater than 0",
+        );
+        let idempotency_max_entries = env.parse("IDEMPOTENCY_MAX_ENTRIES", "a number", 10_000);
+        env.check(
+            idempotency_max_entries > 0,
+            "IDEMPOTENCY_MAX_ENTRIES must be greater than 0",
+        );
+
+        if !env.errors.is_empty() {
+            return Err(ConfigError(env.errors));
+        }
+        Ok(Config {
+            server,
+            db,
+            cors,
+            rate_limit,
+            compression,
+            api_tokens,
+            bcrypt_cost,
+            idempotency_ttl,
+            idempotency_max_entries,
+        })
+    }
+}
+
+impl ServerConfig {
+    fn load(env: &mut Loader) -> ServerConfig {
+        let host = env.parse(
+            "SERVER_HOST",
+            "an IP address (e.g. 0.0.0.0 or ::1)",
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        );
+        let port = match env.parse_opt("SERVER_PORT", "a port number (0-65535)") {
+            Some(port) => port,
+            None => env.parse("PORT", "a port number (0-65535)", 8080),
+        };
+        let uds = env.string("SERVER_UDS");
+        env.check(
+            uds.is_none() || cfg!(unix),
+            "SERVER_UDS is only supported on Unix",
+        );
+        let cores = env.parse_opt("CORES", "a number").unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        });
+        env.check(cores > 0, "CORES must be greater than 0");
+
+        let tls = match (env.string("TLS_CERT"), env.string("TLS_KEY")) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            _ => {
+                env.check(false, "TLS_CERT and TLS_KEY must be set together");
+                None
+            }
+        };
+
+        env.check(
+            uds.is_none() || tls.is_none(),
+            "TLS_CERT/TLS_KEY can't be combined with SERVER_UDS; terminate TLS at the proxy",
+        );
+
+        let log_json = match env.string("LOG_FORMAT") {
+            None => false,
+            Some(format) if format.eq_ignore_ascii_case("json") => true,
+            Some(format) if format.eq_ignore_ascii_case("pretty") => false,
+            Some(format) => {
+                env.check(
+                    false,
+                    format!("LOG_FORMAT='{}' must be pretty or json", format),
+                );
+                false
+            }
+        };
+
+        let log_redact_headers =
+            redacted_headers(&env.list("LOG_REDACT_HEADERS").unwrap_or_default());
+
+        let mut trusted_proxies = Vec::new();
+        for proxy in env.list("TRUSTED_PROXIES").unwrap_or_default() {
+            match proxy.parse::<IpAddr>() {
+                Ok(ip) => trusted_proxies.push(ip),
+                Err(_) => env.check(
+                    false,
+                    format!("TRUSTED_PROXIES: '{}' is not an IP address", proxy),
+                ),
+            }
+        }
+
+        let metrics_path = env.string_or("METRICS_PATH", "/metrics");
+        env.check(
+            metrics_path.starts_with('/'),
+            format!("METRICS_PATH='{}' must start with /", metrics_path),
+        );
+        let metrics_port = env.parse_opt("METRICS_PORT", "a port number (0-65535)");
+        env.check(
+            metrics_port.is_none() || metrics_port != Some(port),
+            "METRICS_PORT must differ from SERVER_PORT",
+        );
+
+        ServerConfig {
+            host,
+            port,
+            uds,
+            cores,
+            tls,
+            keep_alive_timeout: env.secs("KEEP_ALIVE_TIMEOUT_SECS", 5),
+            keep_alive_max_requests: env.parse("KEEP_ALIVE_MAX_REQUESTS", "a number", 100),
+            request_read_timeout: env.secs("REQUEST_READ_TIMEOUT_SECS", 30),
+            shutdown_timeout: env.secs("SHUTDOWN_TIMEOUT_SECS", 30),
+            max_body_size: env.parse("MAX_BODY_SIZE", "a size in bytes", 2 * 1024 * 1024),
+            request_id_header: env.string_or("REQUEST_ID_HEADER", "X-Request-Id"),
+            trust_proxy: env.flag("TRUST_PROXY", false),
+            trusted_proxies,
+            log_json,
+            log_redact_headers,
+            tracing: env.flag("TRACING", false),
+            metrics_path,
+            metrics_port,
+            static_dir: env.string_or("STATIC_DIR", "public"),
+            static_max_age: env.parse("STATIC_MAX_AGE", "a number of seconds", 3600),
+        }
+    }
+}
+
+/// Headers that always carry credentials, so they are masked even when
+/// `LOG_REDACT_HEADERS` lists others.
+const DEFAULT_REDACTED_HEADERS: [&str; 4] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-api-key",
+];
+
+/// The default redacted headers plus `extra`, lowercased and deduplicated.
+fn redacted_headers(extra: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = DEFAULT_REDACTED_HEADERS.map(String::from).to_vec();
+    for name in extra {
+        let name = name.to_ascii_lowercase();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+impl DbConfig {
+    fn load(env: &mut Loader) -> DbConfig {
+        let host = env.string("DB_HOST");
+        let name = env.string("DB_NAME");
+        // Platforms like Heroku/Railway inject a full URL; use it untouched so
+        // query params such as `?sslmode=require` are preserved.
+        let url = env.string("DATABASE_URL").unwrap_or_else(|| {
+            let port = env.parse::<u16>("DB_PORT", "a port number (0-65535)", 5432);
+            format!(
+                "postgres://{}:{}@{}:{}/{}",
+                env.string_or("DB_USER", "postgres"),
+                env.string_or("DB_PASS", "postgres"),
+                host.as_deref().unwrap_or("localhost"),
+                port,
+                name.as_deref().unwrap_or("postgres")
+            )
+        });
+
+        let max_connections = env.parse("DB_MAX_CONNECTIONS", "a number", 10u32);
+        env.check(
+            max_connections > 0,
+            "DB_MAX_CONNECTIONS must be greater than 0",
+        );
+        let min_connections = env.parse_opt::<u32>("DB_MIN_CONNECTIONS", "a number");
+        if let Some(min) = min_connections {
+            env.check(
+                min <= max_connections,
+                format!(
+                    "DB_MIN_CONNECTIONS={} exceeds DB_MAX_CONNECTIONS={}",
+                    min, max_connections
+                ),
+            );
+        }
+        let max_per_page = env.parse("DB_MAX_PER_PAGE", "a number", 100i64);
+        env.check(max_per_page > 0, "DB_MAX_PER_PAGE must be greater than 0");
+
+        DbConfig {
+            url,
+            host,
+            name,
+            replica_url: env.string("DB_REPLICA_URL"),
+            replica_host: env.string("DB_REPLICA_HOST"),
+            replica_port: env.parse_opt("DB_REPLICA_PORT", "a port number (0-65535)"),
+            ssl_mode: env.parse_opt(
+                "DB_SSLMODE",
+                "one of disable, allow, prefer, require, verify-ca, verify-full",
+            ),
+            ssl_root_cert: env.string("DB_SSL_ROOT_CERT"),
+            max_connections,
+            min_connections,
+            idle_timeout: env
+                .parse_opt("DB_IDLE_TIMEOUT_SECS", "a number of seconds")
+                .map(Duration::from_secs),
+            max_lifetime: env
+                .parse_opt("DB_MAX_LIFETIME_SECS", "a number of seconds")
+                .map(Duration::from_secs),
+            acquire_timeout: env.millis("DB_ACQUIRE_TIMEOUT_MS", 30_000),
+            connect_timeout: env.millis("DB_CONNECT_TIMEOUT_MS", 10_000),
+            connect_retries: env.parse("DB_CONNECT_RETRIES", "a number", 5),
+            connect_backoff: env.millis("DB_CONNECT_BACKOFF_MS", 500),
+            max_per_page,
+            slow_query: env
+                .parse_opt("DB_SLOW_QUERY_MS", "a number of milliseconds")
+                .map(Duration::from_millis),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_headers_extend_the_defaults() {
+        let extra = ["X-Session".to_string(), "Cookie".to_string()];
+
+        assert_eq!(
+            redacted_headers(&extra),
+            [
+                "authorization",
+                "proxy-authorization",
+                "cookie",
+                "x-api-key",
+                "x-session"
+            ]
+        );
+        assert_eq!(redacted_headers(&[]), DEFAULT_REDACTED_HEADERS);
+    }
+}
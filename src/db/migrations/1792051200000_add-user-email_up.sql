@@ -0,0 +1 @@
+ALTER TABLE "USER" ADD COLUMN IF NOT EXISTS email TEXT;
@@ -1,18 +1,58 @@
+use crate::config::{self, DbConfig};
+use crate::util::future::BoxFuture;
+use sha2::{Digest, Sha256};
 use sqlx::PgPool;
 use sqlx::Row;
-use sqlx::postgres::{PgPoolOptions, PgRow};
-use std::env;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{PgArguments, PgConnectOptions, PgDatabaseError, PgPoolOptions, PgRow};
+use sqlx::query::Query;
+use sqlx::{Connection, PgConnection, Postgres, Transaction};
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::OnceLock;
+use std::time::Instant;
+use tokio::time::{Duration, sleep, timeout};
+use tracing::Instrument;
+
+pub mod splitter;
 
 static POOL: OnceLock<PgPool> = OnceLock::new();
+static REPLICA_POOL: OnceLock<PgPool> = OnceLock::new();
+
+fn build_connect_options(config: &DbConfig) -> Result<PgConnectOptions, sqlx::Error> {
+    Ok(with_ssl_options(config.url.parse()?, config))
+}
+
+/// The read replica, from `DB_REPLICA_URL` or, failing that, the primary's
+/// settings with `DB_REPLICA_HOST`/`DB_REPLICA_PORT` swapped in. `None` when
+/// neither is set.
+fn build_replica_connect_options(
+    config: &DbConfig,
+) -> Option<Result<PgConnectOptions, sqlx::Error>> {
+    if let Some(url) = &config.replica_url {
+        return Some(url.parse().map(|options| with_ssl_options(options, config)));
+    }
+    let host = config.replica_host.as_deref()?;
+    Some(build_connect_options(config).map(|options| {
+        let options = options.host(host);
+        match config.replica_port {
+            Some(port) => options.port(port),
+            None => options,
+        }
+    }))
+}
+
+fn with_ssl_options(mut options: PgConnectOptions, config: &DbConfig) -> PgConnectOptions {
+    // Managed Postgres (RDS, Supabase, ...) usually requires TLS
+    if let Some(mode) = config.ssl_mode {
+        options = options.ssl_mode(mode);
+    }
 
-fn build_database_url() -> String {
-    let host = env::var("DB_HOST").unwrap_or_else(|_| "localhost".to_string());
-    let port = env::var("DB_PORT").unwrap_or_else(|_| "5432".to_string());
-    let user = env::var("DB_USER").unwrap_or_else(|_| "postgres".to_string());
-    let pass = env::var("DB_PASS").unwrap_or_else(|_| "postgres".to_string());
-    let name = env::var("DB_NAME").unwrap_or_else(|_| "postgres".to_string());
-    format!("postgres://{}:{}@{}:{}/{}", user, pass, host, port, name)
+    if let Some(root_cert) = &config.ssl_root_cert {
+        options = options.ssl_root_cert(root_cert);
+    }
+
+    options
 }
 
 pub async fn init_pool() -> Result<&'static PgPool, sqlx::Error> {
@@ -27,20 +67,38 @@ pub async fn init_pool() -> Result<&'static PgPool, sqlx::Error> {
         return Ok(pool);
     }
 
-    let database_url = build_database_url();
-    let max_connections = env::var("DB_MAX_CONNECTIONS")
-        .ok()
-        .and_then(|v| v.parse::<u32>().ok())
-        .unwrap_or(10);
+    let config = &config::try_get()
+        .map_err(|e| sqlx::Error::Configuration(e.to_string().into()))?
+        .db;
+    let connect_options = build_connect_options(config)?;
+    let max_connections = config.max_connections;
 
     println!("{CYAN}Connecting to database...{RESET}");
 
     println!("{GREEN}Max pool connections:{RESET} {YELLOW}{max_connections}{RESET}");
 
-    let pool = PgPoolOptions::new()
+    let mut options = PgPoolOptions::new()
         .max_connections(max_connections)
-        .connect(&database_url)
-        .await?;
+        .acquire_timeout(config.acquire_timeout);
+
+    if let Some(min_connections) = config.min_connections {
+        options = options.min_connections(min_connections);
+    }
+    if let Some(idle_timeout) = config.idle_timeout {
+        options = options.idle_timeout(idle_timeout);
+    }
+    if let Some(max_lifetime) = config.max_lifetime {
+        options = options.max_lifetime(max_lifetime);
+    }
+
+    let pool = connect_with_retry(&options, connect_options, config).await?;
+
+    if let Some(replica_options) = build_replica_connect_options(config) {
+        println!("{CYAN}Connecting to read replica...{RESET}");
+        let replica = connect_with_retry(&options, replica_options?, config).await?;
+        let _ = REPLICA_POOL.set(replica);
+        println!("{CYAN}Read replica pool initialized successfully!{RESET}");
+    }
 
     println!("{CYAN}DB pool initialized successfully!{RESET}");
 
@@ -48,62 +106,254 @@ pub async fn init_pool() -> Result<&'static PgPool, sqlx::Error> {
     Ok(POOL.get().expect("DB pool initialized"))
 }
 
+/// Connects a pool, retrying with exponential backoff (`DB_CONNECT_RETRIES`,
+/// `DB_CONNECT_BACKOFF_MS`); each attempt is capped at `DB_CONNECT_TIMEOUT_MS`.
+async fn connect_with_retry(
+    options: &PgPoolOptions,
+    connect_options: PgConnectOptions,
+    config: &DbConfig,
+) -> Result<PgPool, sqlx::Error> {
+    with_retries(config.connect_retries, config.connect_backoff, || {
+        connect_once(options, &connect_options, config.connect_timeout)
+    })
+    .await
+}
+
+/// One connection attempt. A single connection is opened first because the
+/// pool retries refused connections internally and then reports only
+/// `PoolTimedOut`; this way the real cause (refused, unknown host, bad
+/// password, ...) is what gets logged and returned.
+async fn connect_once(
+    options: &PgPoolOptions,
+    connect_options: &PgConnectOptions,
+    connect_timeout: Duration,
+) -> Result<PgPool, sqlx::Error> {
+    let timed_out = || {
+        sqlx::Error::Io(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!(
+                "no connection within {}ms (DB_CONNECT_TIMEOUT_MS)",
+                connect_timeout.as_millis()
+            ),
+        ))
+    };
+    let probe = timeout(connect_timeout, PgConnection::connect_with(connect_options))
+        .await
+        .map_err(|_| timed_out())??;
+    let _ = probe.close().await;
+    timeout(
+        connect_timeout,
+        options.clone().connect_with(connect_options.clone()),
+    )
+    .await
+    .map_err(|_| timed_out())?
+}
+
+/// Runs `attempt` until it succeeds or has been retried `retries` times,
+/// waiting `backoff` before the first retry and doubling it each time.
+async fn with_retries<T, F, Fut>(
+    retries: u32,
+    backoff: Duration,
+    mut attempt: F,
+) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    const YELLOW: &str = "\x1b[33m";
+    const RESET: &str = "\x1b[0m";
+
+    let mut retry = 0;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(err) if retry < retries => {
+                let delay = (backoff.as_millis() as u64).saturating_mul(1u64 << retry.min(16));
+                retry += 1;
+                println!(
+                    "{YELLOW}DB connection failed ({err}), retry {retry}/{retries} in {delay}ms...{RESET}"
+                );
+                sleep(Duration::from_millis(delay)).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub fn pool() -> &'static PgPool {
     POOL.get().expect("DB pool not initialized")
 }
 
+/// Pool for read-only `query` calls: the replica when one is configured,
+/// otherwise the primary.
+fn read_pool() -> &'static PgPool {
+    REPLICA_POOL.get().unwrap_or_else(pool)
+}
+
+/// Whether `init_pool` has completed, so callers can skip pool stats early on.
+#[allow(dead_code)]
+pub fn is_initialized() -> bool {
+    POOL.get().is_some()
+}
+
+/// Closes every pooled connection; waits for checked-out connections to be
+/// returned first. No-op if the pool was never initialized.
+pub async fn close() {
+    if let Some(replica) = REPLICA_POOL.get() {
+        replica.close().await;
+    }
+    if let Some(pool) = POOL.get() {
+        pool.close().await;
+    }
+}
+
+#[allow(dead_code)]
+pub async fn ping() -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool()).await?;
+    Ok(())
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStatus {
+    pub size: u32,
+    pub idle: usize,
+}
+
+#[allow(dead_code)]
+pub fn pool_status() -> PoolStatus {
+    let pool = pool();
+    PoolStatus {
+        size: pool.size(),
+        idle: pool.num_idle(),
+    }
+}
+
+/// Upgrades of the bookkeeping tables, applied in order; `_meta` records
+/// how many have run as `schema_version`. Every statement is idempotent, so
+/// databases created before versioning existed upgrade cleanly.
+const BOOKKEEPING_UPGRADES: &[&[&str]] = &[
+    // 1: tracking tables
+    &[
+        "CREATE TABLE IF NOT EXISTS _migrations (\n  id TEXT PRIMARY KEY,\n  name TEXT NOT NULL,\n  applied_at TIMESTAMP NOT NULL DEFAULT NOW()\n)",
+        "CREATE TABLE IF NOT EXISTS _seeders (\n  id TEXT PRIMARY KEY,\n  name TEXT NOT NULL,\n  applied_at TIMESTAMP NOT NULL DEFAULT NOW()\n)",
+    ],
+    // 2: script checksums
+    &[
+        "ALTER TABLE _migrations ADD COLUMN IF NOT EXISTS checksum TEXT",
+        "ALTER TABLE _seeders ADD COLUMN IF NOT EXISTS checksum TEXT",
+    ],
+    // 3: execution time
+    &[
+        "ALTER TABLE _migrations ADD COLUMN IF NOT EXISTS execution_time_ms BIGINT",
+        "ALTER TABLE _seeders ADD COLUMN IF NOT EXISTS execution_time_ms BIGINT",
+    ],
+];
+
+/// Advisory lock key serializing bookkeeping upgrades ("_meta" in ASCII).
+/// Distinct from `MIGRATION_LOCK_KEY` so a long migration run doesn't block
+/// another runner from reaching its own lock wait.
+const BOOKKEEPING_LOCK_KEY: i64 = 0x005f_6d65_7461;
+
+/// Creates the `_meta`, `_migrations` and `_seeders` tables and brings them
+/// up to the current bookkeeping schema version.
 #[allow(dead_code)]
 pub async fn ensure_migrations_tables() -> Result<(), sqlx::Error> {
+    let mut tx = begin().await?;
+    // Released at commit; concurrent runners upgrade one at a time
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(BOOKKEEPING_LOCK_KEY)
+        .execute(&mut *tx)
+        .await?;
+
     sqlx::query(
-        "CREATE TABLE IF NOT EXISTS _migrations (\n  id TEXT PRIMARY KEY,\n  name TEXT NOT NULL,\n  applied_at TIMESTAMP NOT NULL DEFAULT NOW()\n);",
+        "CREATE TABLE IF NOT EXISTS _meta (\n  key TEXT PRIMARY KEY,\n  value TEXT NOT NULL\n)",
     )
-    .execute(pool())
+    .execute(&mut *tx)
     .await?;
+    let current: usize =
+        sqlx::query_scalar::<_, String>("SELECT value FROM _meta WHERE key = 'schema_version'")
+            .fetch_optional(&mut *tx)
+            .await?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
 
+    if current >= BOOKKEEPING_UPGRADES.len() {
+        return tx.commit().await;
+    }
+    for statements in &BOOKKEEPING_UPGRADES[current..] {
+        for statement in *statements {
+            sqlx::query(statement).execute(&mut *tx).await?;
+        }
+    }
     sqlx::query(
-        "CREATE TABLE IF NOT EXISTS _seeders (\n  id TEXT PRIMARY KEY,\n  name TEXT NOT NULL,\n  applied_at TIMESTAMP NOT NULL DEFAULT NOW()\n);",
+        "INSERT INTO _meta (key, value) VALUES ('schema_version', $1)\n\
+         ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
     )
-    .execute(pool())
+    .bind(BOOKKEEPING_UPGRADES.len().to_string())
+    .execute(&mut *tx)
     .await?;
-
-    Ok(())
+    tx.commit().await
 }
 
 #[allow(dead_code)]
-pub async fn mark_migration_applied(id: &str, name: &str) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO _migrations (id, name) VALUES ($1, $2)")
-        .bind(id)
-        .bind(name)
-        .execute(pool())
-        .await?;
+pub async fn mark_migration_applied(
+    conn: &mut PgConnection,
+    id: &str,
+    name: &str,
+    checksum: &str,
+    execution_time_ms: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO _migrations (id, name, checksum, execution_time_ms) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(checksum)
+    .bind(execution_time_ms)
+    .execute(conn)
+    .await?;
     Ok(())
 }
 
 #[allow(dead_code)]
-pub async fn unmark_migration_applied(id: &str) -> Result<(), sqlx::Error> {
+pub async fn unmark_migration_applied(
+    conn: &mut PgConnection,
+    id: &str,
+) -> Result<(), sqlx::Error> {
     sqlx::query("DELETE FROM _migrations WHERE id = $1")
         .bind(id)
-        .execute(pool())
+        .execute(conn)
         .await?;
     Ok(())
 }
 
 #[allow(dead_code)]
-pub async fn mark_seed_applied(id: &str, name: &str) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO _seeders (id, name) VALUES ($1, $2)")
-        .bind(id)
-        .bind(name)
-        .execute(pool())
-        .await?;
+pub async fn mark_seed_applied(
+    conn: &mut PgConnection,
+    id: &str,
+    name: &str,
+    checksum: &str,
+    execution_time_ms: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO _seeders (id, name, checksum, execution_time_ms) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(checksum)
+    .bind(execution_time_ms)
+    .execute(conn)
+    .await?;
     Ok(())
 }
 
 #[allow(dead_code)]
-pub async fn unmark_seed_applied(id: &str) -> Result<(), sqlx::Error> {
+pub async fn unmark_seed_applied(conn: &mut PgConnection, id: &str) -> Result<(), sqlx::Error> {
     sqlx::query("DELETE FROM _seeders WHERE id = $1")
         .bind(id)
-        .execute(pool())
+        .execute(conn)
         .await?;
     Ok(())
 }
@@ -119,6 +369,41 @@ pub async fn applied_migration_ids() -> Result<Vec<String>, sqlx::Error> {
         .collect())
 }
 
+/// Recorded checksum per applied migration id. Rows applied before checksums
+/// were tracked have `None`, as do all rows of a `_migrations` table that
+/// `ensure_migrations_tables` hasn't upgraded yet (e.g. during a dry run).
+#[allow(dead_code)]
+pub async fn applied_migration_checksums() -> Result<HashMap<String, Option<String>>, sqlx::Error> {
+    let rows = match sqlx::query("SELECT id, checksum FROM _migrations")
+        .fetch_all(pool())
+        .await
+    {
+        Ok(rows) => rows,
+        // 42703 = undefined_column: the table predates the checksum column
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42703") => {
+            let ids = applied_migration_ids().await?;
+            return Ok(ids.into_iter().map(|id| (id, None)).collect());
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| {
+            let id = r.try_get::<String, _>("id").ok()?;
+            let checksum = r.try_get::<Option<String>, _>("checksum").ok()?;
+            Some((id, checksum))
+        })
+        .collect())
+}
+
+#[allow(dead_code)]
+pub fn sql_checksum(sql: &str) -> String {
+    Sha256::digest(sql.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 #[allow(dead_code)]
 pub async fn applied_seed_ids() -> Result<Vec<String>, sqlx::Error> {
     let rows = sqlx::query("SELECT id FROM _seeders")
@@ -131,9 +416,149 @@ pub async fn applied_seed_ids() -> Result<Vec<String>, sqlx::Error> {
 }
 
 #[allow(dead_code)]
-pub async fn execute_sql(sql: &str) -> Result<(), sqlx::Error> {
-    sqlx::query(sql).execute(pool()).await?;
-    Ok(())
+#[derive(Debug, Clone)]
+pub struct AppliedScript {
+    pub id: String,
+    pub name: String,
+    pub applied_at: chrono::NaiveDateTime,
+    /// How long the script took; `None` for rows applied before this was
+    /// recorded.
+    pub execution_time_ms: Option<i64>,
+}
+
+async fn applied_scripts(table: &str) -> Result<Vec<AppliedScript>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT id, name, applied_at, execution_time_ms FROM {} ORDER BY length(id), id",
+        table
+    ))
+    .fetch_all(pool())
+    .await?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(AppliedScript {
+                id: r.try_get("id")?,
+                name: r.try_get("name")?,
+                applied_at: r.try_get("applied_at")?,
+                execution_time_ms: r.try_get("execution_time_ms")?,
+            })
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+pub async fn applied_migrations() -> Result<Vec<AppliedScript>, sqlx::Error> {
+    applied_scripts("_migrations").await
+}
+
+#[allow(dead_code)]
+pub async fn applied_seeds() -> Result<Vec<AppliedScript>, sqlx::Error> {
+    applied_scripts("_seeders").await
+}
+
+#[allow(dead_code)]
+pub async fn execute_sql(sql: &str) -> Result<u64, sqlx::Error> {
+    async {
+        let started = Instant::now();
+        let mut conn = pool().acquire().await?;
+        let result = execute_sql_on(&mut conn, sql).await;
+        log_if_slow(sql, &[], started.elapsed());
+        result
+    }
+    .instrument(db_span("execute_sql", sql))
+    .await
+}
+
+#[allow(dead_code)]
+pub async fn execute_sql_on(conn: &mut PgConnection, sql: &str) -> Result<u64, sqlx::Error> {
+    let mut affected = 0;
+    for statement in splitter::split_statements(sql) {
+        let result = sqlx::query(&statement).execute(&mut *conn).await?;
+        affected += result.rows_affected();
+    }
+    Ok(affected)
+}
+
+/// Advisory lock key shared by every migration/seeder runner ("migrate" in ASCII).
+#[allow(dead_code)]
+pub const MIGRATION_LOCK_KEY: i64 = 0x006d_6967_7261_7465;
+
+/// A session-level advisory lock held on a dedicated pooled connection.
+#[allow(dead_code)]
+pub struct AdvisoryLock {
+    conn: PoolConnection<Postgres>,
+    key: i64,
+}
+
+#[allow(dead_code)]
+impl AdvisoryLock {
+    pub async fn release(mut self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT pg_advisory_unlock($1)")
+            .bind(self.key)
+            .execute(&mut *self.conn)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Polls `pg_try_advisory_lock` until the lock is taken or `wait` elapses,
+/// returning `None` on timeout.
+#[allow(dead_code)]
+pub async fn try_advisory_lock(
+    key: i64,
+    wait: Duration,
+) -> Result<Option<AdvisoryLock>, sqlx::Error> {
+    let mut conn = pool().acquire().await?;
+    let deadline = tokio::time::Instant::now() + wait;
+    loop {
+        let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(key)
+            .fetch_one(&mut *conn)
+            .await?;
+        if locked {
+            return Ok(Some(AdvisoryLock { conn, key }));
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Ok(None);
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+}
+
+#[allow(dead_code)]
+pub async fn begin() -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    pool().begin().await
+}
+
+/// Runs `f` inside a transaction on the primary, committing when it returns
+/// `Ok` and rolling back when it returns `Err`, so its writes land together
+/// or not at all. Use `execute_on`/`query_on` with the connection it gets:
+///
+/// ```ignore
+/// db::with_transaction(|tx| Box::pin(async move {
+///     db::execute_on(tx, "UPDATE accounts SET balance = balance - $1 WHERE id = $2", debit).await?;
+///     db::execute_on(tx, "UPDATE accounts SET balance = balance + $1 WHERE id = $2", credit).await?;
+///     Ok::<_, sqlx::Error>(())
+/// })).await?;
+/// ```
+#[allow(dead_code)]
+pub async fn with_transaction<T, E, F>(f: F) -> Result<T, E>
+where
+    F: for<'c> FnOnce(&'c mut PgConnection) -> BoxFuture<'c, Result<T, E>>,
+    E: From<sqlx::Error>,
+{
+    let mut tx = begin().await?;
+    match f(&mut tx).await {
+        Ok(value) => {
+            tx.commit().await?;
+            Ok(value)
+        }
+        Err(err) => {
+            // Dropping the transaction would roll back too, but only once the
+            // connection is reused; do it now so locks are released promptly
+            let _ = tx.rollback().await;
+            Err(err)
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -141,22 +566,663 @@ pub async fn execute_sql(sql: &str) -> Result<(), sqlx::Error> {
 pub enum DbParam {
     Int32(i32),
     Int64(i64),
+    /// Binds as `float8`. Floats can't represent most decimal fractions
+    /// exactly, so never use this for money; use `Decimal`
     Float64(f64),
+    /// Binds as `numeric`, exactly, e.g. for money columns
+    Decimal(rust_decimal::Decimal),
     Bool(bool),
     Text(String),
+    Uuid(uuid::Uuid),
+    Timestamp(chrono::DateTime<chrono::Utc>),
+    Date(chrono::NaiveDate),
+    Json(serde_json::Value),
+    /// Binds as `int4[]`, e.g. for `WHERE id = ANY($1)`; an empty array
+    /// matches nothing
+    Int32Array(Vec<i32>),
+    /// Binds as `int8[]`
+    Int64Array(Vec<i64>),
+    /// Binds as `text[]`
+    TextArray(Vec<String>),
+    Null(NullKind),
 }
 
+/// Column type used when binding a SQL `NULL`, so Postgres can infer the
+/// parameter type (e.g. `INSERT INTO t (a) VALUES ($1)`).
 #[allow(dead_code)]
-pub async fn query(sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+#[derive(Debug, Clone, Copy)]
+pub enum NullKind {
+    /// `int4`
+    Int32,
+    /// `int8`
+    Int64,
+    /// `float8`
+    Float64,
+    /// `numeric`
+    Decimal,
+    /// `bool`
+    Bool,
+    /// `text`
+    Text,
+    /// `uuid`
+    Uuid,
+    /// `timestamptz`
+    Timestamp,
+    /// `date`
+    Date,
+    /// `jsonb`
+    Json,
+}
+
+impl DbParam {
+    /// Postgres type the parameter binds as, e.g. `int4` or `text[]`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            DbParam::Int32(_) | DbParam::Null(NullKind::Int32) => "int4",
+            DbParam::Int64(_) | DbParam::Null(NullKind::Int64) => "int8",
+            DbParam::Float64(_) | DbParam::Null(NullKind::Float64) => "float8",
+            DbParam::Decimal(_) | DbParam::Null(NullKind::Decimal) => "numeric",
+            DbParam::Bool(_) | DbParam::Null(NullKind::Bool) => "bool",
+            DbParam::Text(_) | DbParam::Null(NullKind::Text) => "text",
+            DbParam::Uuid(_) | DbParam::Null(NullKind::Uuid) => "uuid",
+            DbParam::Timestamp(_) | DbParam::Null(NullKind::Timestamp) => "timestamptz",
+            DbParam::Date(_) | DbParam::Null(NullKind::Date) => "date",
+            DbParam::Json(_) | DbParam::Null(NullKind::Json) => "jsonb",
+            DbParam::Int32Array(_) => "int4[]",
+            DbParam::Int64Array(_) => "int8[]",
+            DbParam::TextArray(_) => "text[]",
+        }
+    }
+}
+
+fn bind_params(sql: &str, params: Vec<DbParam>) -> Query<'_, Postgres, PgArguments> {
     let mut q = sqlx::query(sql);
     for param in params {
         q = match param {
             DbParam::Int32(v) => q.bind(v),
             DbParam::Int64(v) => q.bind(v),
             DbParam::Float64(v) => q.bind(v),
+            DbParam::Decimal(v) => q.bind(v),
             DbParam::Bool(v) => q.bind(v),
             DbParam::Text(v) => q.bind(v),
+            DbParam::Uuid(v) => q.bind(v),
+            DbParam::Timestamp(v) => q.bind(v),
+            DbParam::Date(v) => q.bind(v),
+            DbParam::Json(v) => q.bind(v),
+            DbParam::Int32Array(v) => q.bind(v),
+            DbParam::Int64Array(v) => q.bind(v),
+            DbParam::TextArray(v) => q.bind(v),
+            DbParam::Null(kind) => match kind {
+                NullKind::Int32 => q.bind(None::<i32>),
+                NullKind::Int64 => q.bind(None::<i64>),
+                NullKind::Float64 => q.bind(None::<f64>),
+                NullKind::Decimal => q.bind(None::<rust_decimal::Decimal>),
+                NullKind::Bool => q.bind(None::<bool>),
+                NullKind::Text => q.bind(None::<String>),
+                NullKind::Uuid => q.bind(None::<uuid::Uuid>),
+                NullKind::Timestamp => q.bind(None::<chrono::DateTime<chrono::Utc>>),
+                NullKind::Date => q.bind(None::<chrono::NaiveDate>),
+                NullKind::Json => q.bind(None::<serde_json::Value>),
+            },
         };
     }
-    q.fetch_all(pool()).await
+    q
+}
+
+/// Runs a read-only statement on the read replica (or the primary when none
+/// is configured). Replicas lag behind the primary, so a row written moments
+/// ago may not be visible yet; use `query_primary` when that matters.
+#[allow(dead_code)]
+pub async fn query(sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+    fetch_all_on(read_pool(), "query", sql, params).await
+}
+
+/// `query` against the primary, for writes with `RETURNING` and for reads
+/// that must see the caller's own writes.
+#[allow(dead_code)]
+pub async fn query_primary(sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+    fetch_all_on(pool(), "query_primary", sql, params).await
+}
+
+async fn fetch_all_on(
+    pool: &PgPool,
+    operation: &'static str,
+    sql: &str,
+    params: Vec<DbParam>,
+) -> Result<Vec<PgRow>, sqlx::Error> {
+    let types = param_types(&params);
+    let started = Instant::now();
+    let result = bind_params(sql, params)
+        .fetch_all(pool)
+        .instrument(db_span(operation, sql))
+        .await;
+    log_if_slow(sql, &types, started.elapsed());
+    result
+}
+
+#[allow(dead_code)]
+pub async fn execute(sql: &str, params: Vec<DbParam>) -> Result<u64, sqlx::Error> {
+    let types = param_types(&params);
+    let started = Instant::now();
+    let result = bind_params(sql, params)
+        .execute(pool())
+        .instrument(db_span("execute", sql))
+        .await;
+    log_if_slow(sql, &types, started.elapsed());
+    Ok(result?.rows_affected())
+}
+
+/// `execute` on a given connection, e.g. inside `with_transaction`.
+#[allow(dead_code)]
+pub async fn execute_on(
+    conn: &mut PgConnection,
+    sql: &str,
+    params: Vec<DbParam>,
+) -> Result<u64, sqlx::Error> {
+    let types = param_types(&params);
+    let started = Instant::now();
+    let result = bind_params(sql, params)
+        .execute(&mut *conn)
+        .instrument(db_span("execute", sql))
+        .await;
+    log_if_slow(sql, &types, started.elapsed());
+    Ok(result?.rows_affected())
+}
+
+/// `query` on a given connection, e.g. to read rows written earlier in the
+/// same transaction.
+#[allow(dead_code)]
+pub async fn query_on(
+    conn: &mut PgConnection,
+    sql: &str,
+    params: Vec<DbParam>,
+) -> Result<Vec<PgRow>, sqlx::Error> {
+    let types = param_types(&params);
+    let started = Instant::now();
+    let result = bind_params(sql, params)
+        .fetch_all(&mut *conn)
+        .instrument(db_span("query", sql))
+        .await;
+    log_if_slow(sql, &types, started.elapsed());
+    result
+}
+
+/// Child span of the current request span for one DB call. Only the SQL text
+/// is recorded, never the bound values.
+fn db_span(operation: &'static str, sql: &str) -> tracing::Span {
+    tracing::info_span!(
+        "db",
+        db.system = "postgresql",
+        db.operation = operation,
+        db.statement = %one_line(sql),
+    )
+}
+
+fn one_line(sql: &str) -> String {
+    sql.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `DB_SLOW_QUERY_MS`, or `None` (the default) to disable slow-query logging.
+fn slow_query_threshold() -> Option<Duration> {
+    config::get().db.slow_query
+}
+
+fn param_types(params: &[DbParam]) -> Vec<&'static str> {
+    params.iter().map(DbParam::type_name).collect()
+}
+
+/// Reports statements slower than `DB_SLOW_QUERY_MS`. Only parameter types
+/// are printed; values may hold passwords or tokens.
+fn log_if_slow(sql: &str, param_types: &[&str], elapsed: Duration) {
+    const YELLOW: &str = "\x1b[33m";
+    const MAGENTA: &str = "\x1b[35m";
+    const RESET: &str = "\x1b[0m";
+
+    let Some(threshold) = slow_query_threshold() else {
+        return;
+    };
+    if elapsed < threshold {
+        return;
+    }
+    crate::util::telemetry::emit(&format!(
+        "{YELLOW}Slow query ({:.2}ms):{RESET} {} {MAGENTA}[{}]{RESET}",
+        elapsed.as_secs_f64() * 1000.0,
+        one_line(sql),
+        param_types.join(", ")
+    ));
+}
+
+/// Like `query`, but Postgres cancels the statement once it runs longer than
+/// `limit` (`SET LOCAL statement_timeout` in a short transaction), so the
+/// connection goes back to the pool healthy. A cancelled query comes back as
+/// an `Io` error of kind `TimedOut`.
+#[allow(dead_code)]
+pub async fn query_timeout(
+    sql: &str,
+    params: Vec<DbParam>,
+    limit: Duration,
+) -> Result<Vec<PgRow>, sqlx::Error> {
+    let mut tx = begin_with_statement_timeout(limit).await?;
+    let rows = bind_params(sql, params)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| statement_timeout_error(e, limit))?;
+    tx.commit().await?;
+    Ok(rows)
+}
+
+/// `execute` with the same statement timeout as `query_timeout`.
+#[allow(dead_code)]
+pub async fn execute_timeout(
+    sql: &str,
+    params: Vec<DbParam>,
+    limit: Duration,
+) -> Result<u64, sqlx::Error> {
+    let mut tx = begin_with_statement_timeout(limit).await?;
+    let result = bind_params(sql, params)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| statement_timeout_error(e, limit))?;
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+async fn begin_with_statement_timeout(
+    limit: Duration,
+) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    let mut tx = begin().await?;
+    // `SET` doesn't take bind parameters; the value is a plain integer
+    let millis = limit.as_millis().max(1);
+    sqlx::query(&format!("SET LOCAL statement_timeout = {}", millis))
+        .execute(&mut *tx)
+        .await?;
+    Ok(tx)
+}
+
+fn statement_timeout_error(err: sqlx::Error, limit: Duration) -> sqlx::Error {
+    // 57014 = query_canceled, raised when statement_timeout fires
+    match &err {
+        sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some("57014") => {
+            sqlx::Error::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("query exceeded timeout of {:?}", limit),
+            ))
+        }
+        _ => err,
+    }
+}
+
+/// Details of a unique-constraint violation, for turning a duplicate insert
+/// into a `409 Conflict` instead of a 500.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct ConstraintInfo {
+    /// Constraint name, e.g. `USER_username_key`.
+    pub constraint: Option<String>,
+    pub table: Option<String>,
+    /// Columns of the violated key, parsed from the error detail
+    /// (`Key (username)=(bob) already exists.`).
+    pub columns: Vec<String>,
+}
+
+/// `Some` when `err` is a Postgres unique violation (SQLSTATE 23505).
+#[allow(dead_code)]
+pub fn as_unique_violation(err: &sqlx::Error) -> Option<ConstraintInfo> {
+    let sqlx::Error::Database(db_err) = err else {
+        return None;
+    };
+    if db_err.code().as_deref() != Some("23505") {
+        return None;
+    }
+    let pg_err = db_err.try_downcast_ref::<PgDatabaseError>();
+    let columns = pg_err
+        .and_then(|e| e.detail())
+        .and_then(|detail| detail.strip_prefix("Key ("))
+        .and_then(|rest| rest.split_once(")="))
+        .map(|(columns, _)| columns.split(',').map(|c| c.trim().to_string()).collect())
+        .unwrap_or_default();
+    Some(ConstraintInfo {
+        constraint: db_err.constraint().map(|c| c.to_string()),
+        table: pg_err.and_then(|e| e.table()).map(|t| t.to_string()),
+        columns,
+    })
+}
+
+/// Maps a result row into a domain type so callers don't repeat `try_get` calls.
+pub trait FromPgRow: Sized {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error>;
+}
+
+/// Reads a `uuid` column, e.g. `db::get_uuid(&row, "id")?`, without spelling
+/// out `row.try_get::<uuid::Uuid, _>("id")`.
+pub fn get_uuid(row: &PgRow, column: &str) -> Result<uuid::Uuid, sqlx::Error> {
+    row.try_get(column)
+}
+
+#[allow(dead_code)]
+pub async fn query_as<T: FromPgRow>(
+    sql: &str,
+    params: Vec<DbParam>,
+) -> Result<Vec<T>, sqlx::Error> {
+    query(sql, params).await?.iter().map(T::from_row).collect()
+}
+
+#[allow(dead_code)]
+pub async fn query_one(sql: &str, params: Vec<DbParam>) -> Result<PgRow, sqlx::Error> {
+    let mut rows = query(sql, params).await?;
+    match rows.len() {
+        0 => Err(sqlx::Error::RowNotFound),
+        1 => Ok(rows.remove(0)),
+        n => Err(sqlx::Error::Protocol(format!(
+            "query_one expected exactly one row, got {}",
+            n
+        ))),
+    }
+}
+
+#[allow(dead_code)]
+pub async fn query_opt(sql: &str, params: Vec<DbParam>) -> Result<Option<PgRow>, sqlx::Error> {
+    let types = param_types(&params);
+    let started = Instant::now();
+    let result = bind_params(sql, params)
+        .fetch_optional(read_pool())
+        .instrument(db_span("query_opt", sql))
+        .await;
+    log_if_slow(sql, &types, started.elapsed());
+    result
+}
+
+/// One page of a list query plus the totals needed to render pagination.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+    pub total_pages: i64,
+}
+
+#[allow(dead_code)]
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            page: self.page,
+            per_page: self.per_page,
+            total_pages: self.total_pages,
+        }
+    }
+}
+
+/// Upper bound for `per_page`, so `?per_page=100000` can't dump a whole table.
+fn max_per_page() -> i64 {
+    config::get().db.max_per_page
+}
+
+/// Runs `base_sql` (without `LIMIT`/`OFFSET`; add an `ORDER BY` for stable
+/// pages) for the 1-based `page`, along with a `COUNT(*)` over the same query.
+#[allow(dead_code)]
+pub async fn paginate(
+    base_sql: &str,
+    params: Vec<DbParam>,
+    page: i64,
+    per_page: i64,
+) -> Result<Page<PgRow>, sqlx::Error> {
+    let page = page.max(1);
+    let per_page = per_page.clamp(1, max_per_page());
+    let base_sql = base_sql.trim().trim_end_matches(';');
+
+    let count_sql = format!(
+        "SELECT COUNT(*) AS total FROM ({}) AS _page_count",
+        base_sql
+    );
+    let total: i64 = query_one(&count_sql, params.clone())
+        .await?
+        .try_get("total")?;
+
+    let page_sql = format!(
+        "{} LIMIT ${} OFFSET ${}",
+        base_sql,
+        params.len() + 1,
+        params.len() + 2
+    );
+    let mut page_params = params;
+    page_params.push(DbParam::Int64(per_page));
+    page_params.push(DbParam::Int64((page - 1).saturating_mul(per_page)));
+    let items = query(&page_sql, page_params).await?;
+
+    Ok(Page {
+        items,
+        total,
+        page,
+        per_page,
+        total_pages: (total + per_page - 1) / per_page,
+    })
+}
+
+/// Direction of a keyset page.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// One keyset page. `next_cursor` is the cursor column's value in the last
+/// row, or `None` once there are no more rows.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<DbParam>,
+}
+
+/// Keyset pagination: rows of `base_sql` whose `cursor_column` comes after
+/// `cursor` in `order` (the first page when `cursor` is `None`), at most
+/// `limit` of them. Unlike `paginate`, deep pages cost the same as the first,
+/// as long as `cursor_column` is indexed. It must be unique and non-null
+/// (e.g. the primary key), or rows sharing a value can be skipped.
+#[allow(dead_code)]
+pub async fn paginate_after(
+    base_sql: &str,
+    params: Vec<DbParam>,
+    cursor_column: &str,
+    cursor: Option<DbParam>,
+    limit: i64,
+    order: SortOrder,
+) -> Result<CursorPage<PgRow>, sqlx::Error> {
+    let limit = limit.clamp(1, max_per_page());
+    let base_sql = base_sql.trim().trim_end_matches(';');
+    let column = quote_ident(cursor_column);
+    let (comparison, direction) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+
+    let mut params = params;
+    let mut sql = format!("SELECT * FROM ({}) AS _keyset", base_sql);
+    if let Some(cursor) = cursor {
+        params.push(cursor);
+        sql.push_str(&format!(
+            " WHERE {} {} ${}",
+            column,
+            comparison,
+            params.len()
+        ));
+    }
+    // One extra row tells whether another page follows
+    params.push(DbParam::Int64(limit + 1));
+    sql.push_str(&format!(
+        " ORDER BY {} {} LIMIT ${}",
+        column,
+        direction,
+        params.len()
+    ));
+
+    let mut items = query(&sql, params).await?;
+    let has_more = items.len() as i64 > limit;
+    items.truncate(limit as usize);
+    let next_cursor = match items.last() {
+        Some(row) if has_more => Some(column_param(row, cursor_column)?),
+        _ => None,
+    };
+    Ok(CursorPage { items, next_cursor })
+}
+
+/// Reads `column` back as the `DbParam` matching its Postgres type, so it
+/// can be bound as the next cursor.
+fn column_param(row: &PgRow, column: &str) -> Result<DbParam, sqlx::Error> {
+    use sqlx::{Column, TypeInfo};
+
+    let type_name = row
+        .columns()
+        .iter()
+        .find(|c| c.name() == column)
+        .ok_or_else(|| sqlx::Error::ColumnNotFound(column.to_string()))?
+        .type_info()
+        .name()
+        .to_string();
+    Ok(match type_name.as_str() {
+        "INT2" => DbParam::Int32(row.try_get::<i16, _>(column)?.into()),
+        "INT4" => DbParam::Int32(row.try_get(column)?),
+        "INT8" => DbParam::Int64(row.try_get(column)?),
+        "FLOAT8" => DbParam::Float64(row.try_get(column)?),
+        "NUMERIC" => DbParam::Decimal(row.try_get(column)?),
+        "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" => DbParam::Text(row.try_get(column)?),
+        "UUID" => DbParam::Uuid(row.try_get(column)?),
+        "TIMESTAMPTZ" => DbParam::Timestamp(row.try_get(column)?),
+        "DATE" => DbParam::Date(row.try_get(column)?),
+        other => {
+            return Err(sqlx::Error::Protocol(format!(
+                "paginate_after can't use a {} column as cursor",
+                other
+            )));
+        }
+    })
+}
+
+/// Postgres caps a statement at 65535 bind parameters.
+const MAX_BIND_PARAMS: usize = 65535;
+
+fn quote_ident(ident: &str) -> String {
+    ident
+        .split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Inserts every row with multi-row `INSERT ... VALUES ($1, $2), ($3, $4)`
+/// statements, chunked to stay under the bind-parameter limit. All chunks run
+/// in one transaction. `table` may be schema-qualified (`audit.events`).
+#[allow(dead_code)]
+pub async fn insert_many(
+    table: &str,
+    columns: &[&str],
+    rows: Vec<Vec<DbParam>>,
+) -> Result<u64, sqlx::Error> {
+    if rows.is_empty() {
+        return Ok(0);
+    }
+    if columns.is_empty() {
+        return Err(sqlx::Error::Protocol(
+            "insert_many needs at least one column".to_string(),
+        ));
+    }
+    if let Some(row) = rows.iter().find(|row| row.len() != columns.len()) {
+        return Err(sqlx::Error::Protocol(format!(
+            "insert_many row has {} values, expected {}",
+            row.len(),
+            columns.len()
+        )));
+    }
+
+    let prefix = format!(
+        "INSERT INTO {} ({}) VALUES ",
+        quote_ident(table),
+        columns
+            .iter()
+            .map(|c| quote_ident(c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let rows_per_chunk = MAX_BIND_PARAMS / columns.len();
+
+    let mut tx = begin().await?;
+    let mut affected = 0;
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        let chunk: Vec<Vec<DbParam>> = rows.by_ref().take(rows_per_chunk).collect();
+        let mut sql = prefix.clone();
+        let mut n = 0;
+        for (i, row) in chunk.iter().enumerate() {
+            if i > 0 {
+                sql.push_str(", ");
+            }
+            let placeholders: Vec<String> = row
+                .iter()
+                .map(|_| {
+                    n += 1;
+                    format!("${}", n)
+                })
+                .collect();
+            sql.push_str(&format!("({})", placeholders.join(", ")));
+        }
+        let params = chunk.into_iter().flatten().collect();
+        affected += bind_params(&sql, params)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    }
+    tx.commit().await?;
+    Ok(affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn retries_then_returns_the_connect_error() {
+        // Nothing listens on port 1, so every attempt is refused straight away
+        let connect_options = PgConnectOptions::new().host("127.0.0.1").port(1);
+        let options = PgPoolOptions::new();
+        let mut attempts = 0;
+
+        let result = with_retries(2, Duration::from_millis(1), || {
+            attempts += 1;
+            connect_once(&options, &connect_options, Duration::from_secs(5))
+        })
+        .await;
+
+        assert_eq!(attempts, 3);
+        match result {
+            Err(sqlx::Error::Io(err)) => {
+                assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused)
+            }
+            other => panic!("expected the refused connection, got {:?}", other.err()),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_slow_server_is_reported_as_a_timeout() {
+        // Accepts the TCP connection but never answers the startup message
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connect_options = PgConnectOptions::new().host("127.0.0.1").port(port);
+
+        let result = connect_once(
+            &PgPoolOptions::new(),
+            &connect_options,
+            Duration::from_millis(50),
+        )
+        .await;
+
+        match result {
+            Err(sqlx::Error::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::TimedOut),
+            other => panic!("expected a timeout, got {:?}", other.err()),
+        }
+    }
 }
@@ -0,0 +1,232 @@
+/// Splits a SQL script into individual statements on top-level `;`.
+///
+/// Semicolons inside `'...'` strings (including `E'...'` escapes), `"..."`
+/// identifiers, `$tag$...$tag$` dollar-quoted bodies and `--` / `/* */`
+/// comments are left alone, so `CREATE FUNCTION ... AS $$ ...; ... $$` stays
+/// a single statement. Empty and comment-only statements are dropped.
+pub fn split_statements(sql: &str) -> Vec<String> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut has_code = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' => {
+                let escapes = i > 0
+                    && matches!(bytes[i - 1], b'E' | b'e')
+                    && (i < 2 || !is_ident_byte(bytes[i - 2]));
+                i = skip_quoted(bytes, i, b'\'', escapes);
+                has_code = true;
+            }
+            b'"' => {
+                i = skip_quoted(bytes, i, b'"', false);
+                has_code = true;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+            }
+            b'$' => match dollar_tag(bytes, i) {
+                Some(tag) => {
+                    i = skip_dollar_quoted(bytes, i, tag);
+                    has_code = true;
+                }
+                None => {
+                    i += 1;
+                    has_code = true;
+                }
+            },
+            b';' => {
+                if has_code {
+                    statements.push(sql[start..i].trim().to_string());
+                }
+                i += 1;
+                start = i;
+                has_code = false;
+            }
+            c => {
+                if !c.is_ascii_whitespace() {
+                    has_code = true;
+                }
+                i += 1;
+            }
+        }
+    }
+
+    if has_code {
+        statements.push(sql[start..].trim().to_string());
+    }
+
+    statements
+}
+
+/// Returns the index just past the closing quote. A doubled quote (`''`)
+/// is an escaped quote; with `escapes` set a backslash escapes the next byte.
+fn skip_quoted(bytes: &[u8], open: usize, quote: u8, escapes: bool) -> usize {
+    let mut i = open + 1;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn skip_block_comment(bytes: &[u8], open: usize) -> usize {
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the full opening tag (`$$` or `$name$`) starting at `i`, if any.
+/// Positional parameters like `$1` are not tags, and neither is a `$` that
+/// continues an identifier such as `foo$bar$`.
+fn dollar_tag(bytes: &[u8], i: usize) -> Option<&[u8]> {
+    if i > 0 && is_ident_byte(bytes[i - 1]) {
+        return None;
+    }
+    let mut j = i + 1;
+    while j < bytes.len() {
+        let c = bytes[j];
+        if c == b'$' {
+            return Some(&bytes[i..=j]);
+        }
+        let valid = is_ident_byte(c) && (j > i + 1 || !c.is_ascii_digit());
+        if !valid {
+            return None;
+        }
+        j += 1;
+    }
+    None
+}
+
+fn skip_dollar_quoted(bytes: &[u8], open: usize, tag: &[u8]) -> usize {
+    let mut i = open + tag.len();
+    while i + tag.len() <= bytes.len() {
+        if &bytes[i..i + tag.len()] == tag {
+            return i + tag.len();
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn is_ident_byte(c: u8) -> bool {
+    c == b'_' || c.is_ascii_alphanumeric()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_body_is_not_split() {
+        let sql = "CREATE FUNCTION touch() RETURNS trigger AS $$
+BEGIN
+    NEW.updated_at = now();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+CREATE TABLE t (id int);";
+
+        let statements = split_statements(sql);
+
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].starts_with("CREATE FUNCTION touch()"));
+        assert!(statements[0].ends_with("$$ LANGUAGE plpgsql"));
+        assert_eq!(statements[1], "CREATE TABLE t (id int)");
+    }
+
+    #[test]
+    fn dollar_inside_identifier_is_not_a_tag() {
+        let sql = "SELECT foo$bar$ FROM t; SELECT 1;";
+
+        assert_eq!(
+            split_statements(sql),
+            vec!["SELECT foo$bar$ FROM t", "SELECT 1"]
+        );
+    }
+
+    #[test]
+    fn semicolons_in_strings_and_comments_are_not_separators() {
+        let sql = "INSERT INTO notes (body) VALUES ('one; two');
+-- a comment; with a semicolon
+UPDATE notes SET body = 'x';
+DELETE FROM notes;";
+
+        let statements = split_statements(sql);
+
+        assert_eq!(statements.len(), 3);
+        assert_eq!(
+            statements[0],
+            "INSERT INTO notes (body) VALUES ('one; two')"
+        );
+        assert_eq!(
+            statements[1],
+            "-- a comment; with a semicolon\nUPDATE notes SET body = 'x'"
+        );
+        assert_eq!(statements[2], "DELETE FROM notes");
+    }
+
+    #[test]
+    fn plpgsql_body_stays_one_statement() {
+        let sql = "CREATE FUNCTION bump(n int) RETURNS int AS $$
+DECLARE
+    result int;
+BEGIN
+    result := n + 1;
+    RETURN result;
+END;
+$$ LANGUAGE plpgsql;";
+
+        let statements = split_statements(sql);
+
+        assert_eq!(statements, vec![sql.trim_end_matches(';')]);
+    }
+
+    #[test]
+    fn tagged_body_stays_one_statement() {
+        let sql = "DO $body$
+BEGIN
+    RAISE NOTICE 'done; $$ is not the end';
+END;
+$body$;
+SELECT 1;";
+
+        let statements = split_statements(sql);
+
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].starts_with("DO $body$"));
+        assert!(statements[0].ends_with("END;\n$body$"));
+        assert_eq!(statements[1], "SELECT 1");
+    }
+}
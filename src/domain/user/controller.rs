@@ -1,33 +1,64 @@
 use std::collections::HashMap;
 
+use crate::db;
+use crate::middleware;
+use crate::middlewares::{etag, idempotency, rate_limit};
+use crate::primitives::http::method::Method;
+use crate::primitives::http::problem::Problem;
 use crate::primitives::http::request::Request;
 use crate::primitives::http::response::Response;
+use crate::primitives::http::status::StatusCode;
 use crate::route;
 use crate::routing::{Route, RouteParams};
+use crate::util::telemetry;
 
 use super::repo::UserRepo;
 use super::service::UserService;
 use uuid::Uuid;
 
+/// User payloads are a few short strings, so the write routes refuse
+/// anything bigger before reading it rather than allowing `MAX_BODY_SIZE`.
+const USER_BODY_LIMIT: usize = 16 * 1024;
+
 pub struct UserController;
 
 impl UserController {
     pub fn routes() -> Vec<Route> {
         vec![
-            Route::new("GET", &["user"], vec![route!(UserController::get_all)]),
-            Route::new("POST", &["user"], vec![route!(UserController::create)]),
             Route::new(
-                "GET",
+                Method::Get,
+                &["user"],
+                vec![route!(UserController::get_all)],
+            ),
+            Route::new(
+                Method::Post,
+                &["user"],
+                vec![
+                    middleware!(rate_limit::rate_limit),
+                    middleware!(idempotency::idempotency),
+                    route!(UserController::create),
+                ],
+            )
+            .max_body_size(USER_BODY_LIMIT),
+            Route::new(
+                Method::Get,
                 &["user", ":id"],
-                vec![route!(UserController::get_one)],
+                vec![middleware!(etag::etag), route!(UserController::get_one)],
             ),
             Route::new(
-                "PUT",
+                Method::Put,
                 &["user", ":id"],
                 vec![route!(UserController::update)],
-            ),
+            )
+            .max_body_size(USER_BODY_LIMIT),
             Route::new(
-                "DELETE",
+                Method::Patch,
+                &["user", ":id"],
+                vec![route!(UserController::update)],
+            )
+            .max_body_size(USER_BODY_LIMIT),
+            Route::new(
+                Method::Delete,
                 &["user", ":id"],
                 vec![route!(UserController::delete)],
             ),
@@ -58,18 +89,14 @@ impl UserController {
             Ok(body) => Response {
                 status_code: 200,
                 headers,
-                body,
+                body: body.into(),
             },
 
-            Err(e) => Response {
-                status_code: 500,
-                headers,
-                body: format!("Failed to fetch users: {}", e),
-            },
+            Err(e) => internal_error(_request, "fetch users", e),
         }
     }
 
-    pub async fn get_one(_request: &mut Request, params: &RouteParams) -> Response {
+    pub async fn get_one(request: &mut Request, params: &RouteParams) -> Response {
         let _id = params.get("id").unwrap_or("");
 
         let mut headers = HashMap::new();
@@ -86,7 +113,8 @@ impl UserController {
                         "Invalid UUID for user id: '{}'. Must be a valid UUID string.",
                         _id
                     ))
-                ),
+                )
+                .into(),
             };
         }
 
@@ -95,13 +123,9 @@ impl UserController {
             Ok(body) => Response {
                 status_code: 200,
                 headers,
-                body,
-            },
-            Err(e) => Response {
-                status_code: 500,
-                headers,
-                body: format!("{{\"error\":{}}}", serde_json::json!(e.to_string())),
+                body: body.into(),
             },
+            Err(e) => internal_error(request, "fetch user", e),
         }
     }
 
@@ -109,31 +133,46 @@ impl UserController {
         let mut headers = HashMap::new();
         headers.insert("Content-Type".to_string(), "text/plain".to_string());
 
-        let user = match super::dto::UserDto::from_json(&request.body) {
+        let user = match request
+            .body_string()
+            .and_then(|body| super::dto::UserDto::from_json(&body))
+        {
             Ok(user) => user,
             Err(err) => {
                 return Response {
                     status_code: 400,
                     headers,
-                    body: err,
+                    body: err.into(),
                 };
             }
         };
 
-        let service = UserService::new(UserRepo::new());
-
-        if let Err(e) = service.create_user(user).await {
-            return Response {
-                status_code: 500,
-                headers,
-                body: format!("Failed to create user: {}", e),
-            };
+        if let Err(errors) = user.validate() {
+            return validation_error(errors);
         }
 
-        Response {
-            status_code: 201,
-            headers,
-            body: "".to_string(),
+        let service = UserService::new(UserRepo::new());
+
+        match service.create_user(user).await {
+            Ok(created) => {
+                headers.insert("Content-Type".to_string(), "application/json".to_string());
+                Response {
+                    status_code: 201,
+                    headers,
+                    body: serde_json::to_string(&created).unwrap_or_default().into(),
+                }
+            }
+            Err(e) if db::as_unique_violation(&e).is_some() => {
+                headers.insert("Content-Type".to_string(), "application/json".to_string());
+                Response {
+                    status_code: 409,
+                    headers,
+                    body: serde_json::json!({ "error": "username already taken" })
+                        .to_string()
+                        .into(),
+                }
+            }
+            Err(e) => internal_error(request, "create user", e),
         }
     }
 
@@ -154,38 +193,56 @@ impl UserController {
                         "Invalid UUID for user id: '{}'. Must be a valid UUID string.",
                         _id
                     ))
-                ),
+                )
+                .into(),
             };
         }
 
-        let user = match super::dto::UpdateUserDto::from_json(&request.body) {
+        let user = match request
+            .body_string()
+            .and_then(|body| super::dto::UpdateUserDto::from_json(&body))
+        {
             Ok(user) => user,
             Err(err) => {
                 return Response {
                     status_code: 400,
                     headers,
-                    body: err,
+                    body: err.into(),
                 };
             }
         };
 
+        if let Err(errors) = user.validate() {
+            return validation_error(errors);
+        }
+
         let service = UserService::new(UserRepo::new());
 
-        match service.update_user(_id, user.password).await {
-            Ok(_) => Response {
+        match service.update_user(_id, user).await {
+            Ok(Some(updated)) => Response {
                 status_code: 200,
                 headers,
-                body: "".to_string(),
+                body: serde_json::to_string(&updated).unwrap_or_default().into(),
+            },
+            Ok(None) => Response {
+                status_code: 404,
+                headers,
+                body: serde_json::json!({ "error": "user not found" })
+                    .to_string()
+                    .into(),
             },
-            Err(e) => Response {
-                status_code: 500,
+            Err(e) if db::as_unique_violation(&e).is_some() => Response {
+                status_code: 409,
                 headers,
-                body: format!("Failed to create user: {}", e),
+                body: serde_json::json!({ "error": "username already taken" })
+                    .to_string()
+                    .into(),
             },
+            Err(e) => internal_error(request, "update user", e),
         }
     }
 
-    pub async fn delete(_request: &mut Request, params: &RouteParams) -> Response {
+    pub async fn delete(request: &mut Request, params: &RouteParams) -> Response {
         let _id = params.get("id").unwrap_or("").to_string();
         let service = UserService::new(UserRepo::new());
 
@@ -203,21 +260,47 @@ impl UserController {
                         "Invalid UUID for user id: '{}'. Must be a valid UUID string.",
                         _id
                     ))
-                ),
+                )
+                .into(),
             };
         }
 
         match service.delete_user(_id).await {
-            Ok(_) => Response {
-                status_code: 200,
+            Ok(0) => Response {
+                status_code: 404,
                 headers,
-                body: "".to_string(),
+                body: format!("{{\"error\":{}}}", serde_json::json!("User not found")).into(),
             },
-            Err(e) => Response {
-                status_code: 500,
+            Ok(_) => Response {
+                status_code: 200,
                 headers,
-                body: format!("Failed to create user: {}", e),
+                body: "".into(),
             },
+            Err(e) => internal_error(request, "delete user", e),
         }
     }
 }
+
+/// A generic 500 for an unexpected database error. The error is logged with
+/// the request id rather than sent, since it can expose SQL and schema details.
+fn internal_error(request: &Request, action: &str, err: sqlx::Error) -> Response {
+    const RED: &str = "\x1b[31m";
+    const RESET: &str = "\x1b[0m";
+    telemetry::emit(&format!(
+        "{RED}Failed to {}:{RESET} {} (request {})",
+        action, err, request.request_id
+    ));
+    Problem::new(StatusCode::INTERNAL_SERVER_ERROR)
+        .detail("An unexpected error occurred; please try again later")
+        .to_response(request)
+}
+
+fn validation_error(errors: Vec<String>) -> Response {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    Response {
+        status_code: 400,
+        headers,
+        body: serde_json::json!({ "errors": errors }).to_string().into(),
+    }
+}
@@ -1,25 +1,364 @@
 use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::postgres::PgRow;
+
+use super::password::hash_password;
+use crate::db::{self, DbParam, FromPgRow};
+use bcrypt::BcryptError;
+
+const USERNAME_MIN_LEN: usize = 3;
+const USERNAME_MAX_LEN: usize = 32;
+const PASSWORD_MIN_LEN: usize = 8;
+const EMAIL_MAX_LEN: usize = 254;
+
 #[derive(Deserialize, Serialize)]
 pub struct UserDto {
     #[serde(default)]
     pub id: String,
     pub username: String,
+    #[serde(skip_serializing)]
     pub password: String,
+    #[serde(default)]
+    pub email: Option<String>,
 }
 
 impl UserDto {
     pub fn from_json(json: &str) -> Result<Self, String> {
         serde_json::from_str(json).map_err(|e| format!("Invalid user JSON: {}", e))
     }
+
+    /// Checks the field rules and reports every violation at once.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        validate_username(&self.username, &mut errors);
+        validate_password(&self.password, &mut errors);
+        if let Some(email) = &self.email {
+            validate_email(email, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Replaces the plaintext password with its bcrypt hash; call this before
+    /// the DTO reaches the repo.
+    pub fn hash_password(&mut self) -> Result<(), BcryptError> {
+        self.password = hash_password(&self.password)?;
+        Ok(())
+    }
+}
+
+impl FromPgRow for UserDto {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: db::get_uuid(row, "id")?.to_string(),
+            username: row.try_get("username")?,
+            password: row.try_get("password")?,
+            email: row.try_get("email")?,
+        })
+    }
+}
+
+/// What the API returns for a user; it has no password field, so a hash can
+/// never leak into a response body.
+#[derive(Serialize)]
+pub struct UserResponseDto {
+    pub id: String,
+    pub username: String,
+    pub email: Option<String>,
+}
+
+impl FromPgRow for UserResponseDto {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: db::get_uuid(row, "id")?.to_string(),
+            username: row.try_get("username")?,
+            email: row.try_get("email")?,
+        })
+    }
 }
 
+impl From<UserDto> for UserResponseDto {
+    fn from(user: UserDto) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+        }
+    }
+}
+
+/// A partial update: only the fields present in the body are changed.
 #[derive(Deserialize, Serialize)]
 pub struct UpdateUserDto {
-    pub password: String,
+    pub username: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    pub email: Option<String>,
 }
 
 impl UpdateUserDto {
     pub fn from_json(json: &str) -> Result<Self, String> {
         serde_json::from_str(json).map_err(|e| format!("Invalid user JSON: {}", e))
     }
+
+    /// Checks the fields that are present; a body with none of them is
+    /// rejected as there is nothing to update.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        if self.username.is_none() && self.password.is_none() && self.email.is_none() {
+            return Err(vec![
+                "nothing to update: provide username, password or email".to_string(),
+            ]);
+        }
+
+        let mut errors = Vec::new();
+        if let Some(username) = &self.username {
+            validate_username(username, &mut errors);
+        }
+        if let Some(password) = &self.password {
+            validate_password(password, &mut errors);
+        }
+        if let Some(email) = &self.email {
+            validate_email(email, &mut errors);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Replaces a provided plaintext password with its bcrypt hash.
+    pub fn hash_password(&mut self) -> Result<(), BcryptError> {
+        if let Some(password) = &self.password {
+            self.password = Some(hash_password(password)?);
+        }
+        Ok(())
+    }
+
+    /// The `SET` list for the present fields, with placeholders numbered
+    /// from `first_param` (e.g. `username = $2, email = $3`), and the values
+    /// to bind for them in order.
+    pub fn set_clause(&self, first_param: usize) -> (String, Vec<DbParam>) {
+        let fields = [
+            ("username", &self.username),
+            ("password", &self.password),
+            ("email", &self.email),
+        ];
+        let mut assignments = Vec::new();
+        let mut params = Vec::new();
+        for (column, value) in fields {
+            if let Some(value) = value {
+                assignments.push(format!("{} = ${}", column, first_param + params.len()));
+                params.push(DbParam::Text(value.clone()));
+            }
+        }
+        (assignments.join(", "), params)
+    }
+}
+
+fn validate_username(username: &str, errors: &mut Vec<String>) {
+    if username.is_empty() {
+        errors.push("username must not be empty".to_string());
+        return;
+    }
+    let len = username.chars().count();
+    if len < USERNAME_MIN_LEN {
+        errors.push(format!(
+            "username must be at least {} characters",
+            USERNAME_MIN_LEN
+        ));
+    }
+    if len > USERNAME_MAX_LEN {
+        errors.push(format!(
+            "username must be at most {} characters",
+            USERNAME_MAX_LEN
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        errors.push("username may only contain letters, digits and underscores".to_string());
+    }
+}
+
+/// A deliberately loose check (`local@domain.tld`); deliverability is only
+/// provable by sending mail.
+fn validate_email(email: &str, errors: &mut Vec<String>) {
+    let valid = email.len() <= EMAIL_MAX_LEN
+        && !email.chars().any(char::is_whitespace)
+        && email.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain
+                    .split_once('.')
+                    .is_some_and(|(name, tld)| !name.is_empty() && !tld.is_empty())
+        });
+    if !valid {
+        errors.push("email must be a valid address like name@example.com".to_string());
+    }
+}
+
+fn validate_password(password: &str, errors: &mut Vec<String>) {
+    if password.trim().is_empty() {
+        errors.push("password must not be blank".to_string());
+    } else if password.chars().count() < PASSWORD_MIN_LEN {
+        errors.push(format!(
+            "password must be at least {} characters",
+            PASSWORD_MIN_LEN
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(
+        username: Option<&str>,
+        password: Option<&str>,
+        email: Option<&str>,
+    ) -> UpdateUserDto {
+        UpdateUserDto {
+            username: username.map(String::from),
+            password: password.map(String::from),
+            email: email.map(String::from),
+        }
+    }
+
+    #[test]
+    fn set_clause_for_username_only() {
+        let (sql, params) = update(Some("new_name"), None, None).set_clause(2);
+
+        assert_eq!(sql, "username = $2");
+        assert_eq!(params.len(), 1);
+        assert!(matches!(&params[0], DbParam::Text(v) if v == "new_name"));
+    }
+
+    #[test]
+    fn set_clause_numbers_present_fields_in_order() {
+        let (sql, params) = update(Some("new_name"), None, Some("a@example.com")).set_clause(2);
+
+        assert_eq!(sql, "username = $2, email = $3");
+        assert!(matches!(&params[..], [DbParam::Text(u), DbParam::Text(e)]
+            if u == "new_name" && e == "a@example.com"));
+    }
+
+    fn user(username: &str, password: &str, email: Option<&str>) -> UserDto {
+        UserDto {
+            id: String::new(),
+            username: username.to_string(),
+            password: password.to_string(),
+            email: email.map(String::from),
+        }
+    }
+
+    fn errors(user: UserDto) -> Vec<String> {
+        user.validate().err().unwrap_or_default()
+    }
+
+    #[test]
+    fn accepts_a_valid_user() {
+        assert!(
+            user("jane_doe", "correct horse", Some("jane@example.com"))
+                .validate()
+                .is_ok()
+        );
+        assert!(user("jane_doe", "correct horse", None).validate().is_ok());
+    }
+
+    #[test]
+    fn username_must_not_be_empty() {
+        assert_eq!(
+            errors(user("", "correct horse", None)),
+            ["username must not be empty"]
+        );
+    }
+
+    #[test]
+    fn username_has_a_minimum_length() {
+        assert_eq!(
+            errors(user("ab", "correct horse", None)),
+            ["username must be at least 3 characters"]
+        );
+    }
+
+    #[test]
+    fn username_has_a_maximum_length() {
+        assert_eq!(
+            errors(user(&"a".repeat(33), "correct horse", None)),
+            ["username must be at most 32 characters"]
+        );
+    }
+
+    #[test]
+    fn username_uses_allowed_characters() {
+        assert_eq!(
+            errors(user("jane-doe", "correct horse", None)),
+            ["username may only contain letters, digits and underscores"]
+        );
+    }
+
+    #[test]
+    fn password_must_not_be_blank() {
+        assert_eq!(
+            errors(user("jane_doe", "   ", None)),
+            ["password must not be blank"]
+        );
+    }
+
+    #[test]
+    fn password_has_a_minimum_length() {
+        assert_eq!(
+            errors(user("jane_doe", "short", None)),
+            ["password must be at least 8 characters"]
+        );
+    }
+
+    #[test]
+    fn email_must_look_like_an_address() {
+        for email in [
+            "jane",
+            "jane@",
+            "@example.com",
+            "jane@example",
+            "jane doe@example.com",
+        ] {
+            assert_eq!(
+                errors(user("jane_doe", "correct horse", Some(email))),
+                ["email must be a valid address like name@example.com"],
+                "{}",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn reports_every_violation_at_once() {
+        assert_eq!(
+            errors(user("a!", "short", Some("nope"))),
+            [
+                "username must be at least 3 characters",
+                "username may only contain letters, digits and underscores",
+                "password must be at least 8 characters",
+                "email must be a valid address like name@example.com",
+            ]
+        );
+    }
+
+    #[test]
+    fn update_needs_at_least_one_field() {
+        assert_eq!(
+            update(None, None, None).validate().unwrap_err(),
+            ["nothing to update: provide username, password or email"]
+        );
+        assert_eq!(
+            update(Some("x"), None, None).validate().unwrap_err(),
+            ["username must be at least 3 characters"]
+        );
+    }
 }
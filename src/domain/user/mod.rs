@@ -1,4 +1,5 @@
 pub mod controller;
 pub mod dto;
+pub mod password;
 pub mod repo;
 pub mod service;
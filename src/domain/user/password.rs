@@ -0,0 +1,20 @@
+use bcrypt::{BcryptError, DEFAULT_COST, hash, verify};
+
+use crate::config;
+
+fn bcrypt_cost() -> u32 {
+    config::get().bcrypt_cost.unwrap_or(DEFAULT_COST)
+}
+
+/// Hashes a plaintext password with bcrypt. Every call generates a fresh
+/// random salt, which is embedded in the returned hash string.
+pub fn hash_password(plain: &str) -> Result<String, BcryptError> {
+    hash(plain, bcrypt_cost())
+}
+
+/// Checks a candidate password against a stored bcrypt hash. A malformed hash
+/// is treated as a mismatch.
+#[allow(dead_code)]
+pub fn verify_password(hash: &str, candidate: &str) -> bool {
+    verify(candidate, hash).unwrap_or(false)
+}
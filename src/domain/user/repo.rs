@@ -3,8 +3,8 @@ use serde_json::Value;
 use sqlx::Row;
 use sqlx::postgres::PgRow;
 
-use super::dto::UserDto;
-use crate::db::{self, DbParam};
+use super::dto::{UpdateUserDto, UserDto};
+use crate::db::{self, DbParam, NullKind};
 use crate::util::pagination::build_paginated_json_query;
 
 impl UserRepo {
@@ -29,8 +29,8 @@ impl UserRepo {
         // Use a CTE to fetch paginated data and total count in one query
         let pagination = build_paginated_json_query(
             "USER",
-            "id, username",
-            "'id', id, 'username', username",
+            "id, username, email",
+            "'id', id, 'username', username, 'email', email",
             where_clause,
             where_params,
             top,
@@ -39,7 +39,7 @@ impl UserRepo {
 
         let rows = db::query(&pagination.sql, pagination.params).await?;
 
-        let (users_json, total_count) = if let Some(row) = rows.get(0) {
+        let (users_json, total_count) = if let Some(row) = rows.first() {
             let users_json = row
                 .try_get::<Value, _>("data_json")
                 .unwrap_or(Value::Array(vec![]));
@@ -66,21 +66,24 @@ impl UserRepo {
     }
 
     pub async fn create(&self, user: UserDto) -> Result<Vec<PgRow>, sqlx::Error> {
-        let res = db::query(
+        db::query_primary(
             "
             INSERT
             INTO
-                \"USER\" (username, password)
+                \"USER\" (username, password, email)
             VALUES
-                ($1, $2)
+                ($1, $2, $3)
             RETURNING
-                id, username, password
+                id, username, email
             ",
-            vec![DbParam::Text(user.username), DbParam::Text(user.password)],
+            vec![
+                DbParam::Text(user.username),
+                DbParam::Text(user.password),
+                user.email
+                    .map_or(DbParam::Null(NullKind::Text), DbParam::Text),
+            ],
         )
-        .await;
-
-        res
+        .await
     }
 
     pub async fn get_one(&self, id: String) -> Result<String, sqlx::Error> {
@@ -89,7 +92,8 @@ impl UserRepo {
                 to_jsonb(
                     json_build_object(
                         'id', id,
-                        'username', username
+                        'username', username,
+                        'email', email
                     )
                 ) AS user_json
             FROM
@@ -98,34 +102,41 @@ impl UserRepo {
                 id = $1::uuid
         ";
 
-        let rows: Vec<PgRow> = db::query(sql, vec![DbParam::Text(id)]).await?;
+        let row: Option<PgRow> = db::query_opt(sql, vec![DbParam::Text(id)]).await?;
 
-        if let Some(row) = rows.get(0) {
-            let value = row.try_get::<Value, _>("user_json").unwrap_or(Value::Null);
-            Ok(value.to_string())
-        } else {
-            Ok("null".to_string())
-        }
+        let value = row
+            .and_then(|row| row.try_get::<Value, _>("user_json").ok())
+            .unwrap_or(Value::Null);
+        Ok(value.to_string())
     }
 
+    /// Applies only the fields present in `user`; returns the updated row,
+    /// or none if no user has this id.
     pub async fn update_user(
         &self,
         id: String,
-        password: String,
+        user: &UpdateUserDto,
     ) -> Result<Vec<PgRow>, sqlx::Error> {
-        let sql: &str = "
+        let (set_clause, mut params) = user.set_clause(2);
+        let sql = format!(
+            "
             UPDATE
                 \"USER\"
             SET
-                password = $2
+                {}
             WHERE
                 id = $1::uuid
-        ";
+            RETURNING
+                id, username, email
+            ",
+            set_clause
+        );
+        params.insert(0, DbParam::Text(id));
 
-        db::query(sql, vec![DbParam::Text(id), DbParam::Text(password)]).await
+        db::query_primary(&sql, params).await
     }
 
-    pub async fn delete_user(&self, id: String) -> Result<Vec<PgRow>, sqlx::Error> {
+    pub async fn delete_user(&self, id: String) -> Result<u64, sqlx::Error> {
         let sql: &str = "
             DELETE
             FROM
@@ -134,6 +145,6 @@ impl UserRepo {
                 id = $1::uuid
         ";
 
-        db::query(sql, vec![DbParam::Text(id)]).await
+        db::execute(sql, vec![DbParam::Text(id)]).await
     }
 }
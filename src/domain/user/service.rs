@@ -1,8 +1,6 @@
-use super::dto::UserDto;
+use super::dto::{UpdateUserDto, UserDto, UserResponseDto};
 use super::repo::UserRepo;
-use bcrypt::{DEFAULT_COST, hash};
-use sqlx::postgres::PgRow;
-use std::env;
+use crate::db::FromPgRow;
 
 pub struct UserService {
     repo: UserRepo,
@@ -22,40 +20,34 @@ impl UserService {
         self.repo.get_all_paginated(top, skip, query).await
     }
 
-    pub async fn create_user(&self, mut user: UserDto) -> Result<(), sqlx::Error> {
+    pub async fn create_user(&self, mut user: UserDto) -> Result<UserResponseDto, sqlx::Error> {
         // Hash the password before saving
-        let cost = env::var("BCRYPT_COST")
-            .ok()
-            .and_then(|v| v.parse::<u32>().ok())
-            .unwrap_or(DEFAULT_COST);
+        user.hash_password()
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
 
-        let hashed = hash(&user.password, cost).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
-
-        user.password = hashed;
-
-        self.repo.create(user).await.map(|_| ())
+        let rows = self.repo.create(user).await?;
+        let row = rows.first().ok_or(sqlx::Error::RowNotFound)?;
+        UserResponseDto::from_row(row)
     }
 
     pub async fn get_one(&self, id: String) -> Result<String, sqlx::Error> {
         self.repo.get_one(id).await
     }
 
+    /// Updates the provided fields; `None` when no user has this id.
     pub async fn update_user(
         &self,
         id: String,
-        password: String,
-    ) -> Result<Vec<PgRow>, sqlx::Error> {
-        let cost = env::var("BCRYPT_COST")
-            .ok()
-            .and_then(|v| v.parse::<u32>().ok())
-            .unwrap_or(DEFAULT_COST);
-
-        let hashed = hash(password, cost).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+        mut user: UpdateUserDto,
+    ) -> Result<Option<UserResponseDto>, sqlx::Error> {
+        user.hash_password()
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
 
-        self.repo.update_user(id, hashed).await
+        let rows = self.repo.update_user(id, &user).await?;
+        rows.first().map(UserResponseDto::from_row).transpose()
     }
 
-    pub async fn delete_user(&self, id: String) -> Result<Vec<PgRow>, sqlx::Error> {
+    pub async fn delete_user(&self, id: String) -> Result<u64, sqlx::Error> {
         self.repo.delete_user(id).await
     }
 }
@@ -1,11 +1,13 @@
 use dotenv::dotenv;
 use std::collections::HashMap;
-use std::env;
+use std::net::SocketAddr;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpListener;
 use tokio::sync::{Semaphore, mpsc};
-use tokio::time::{Duration, sleep};
+use tokio::time::{Duration, Instant, sleep, timeout, timeout_at};
+use tokio_rustls::TlsAcceptor;
 
+mod config;
 mod db;
 mod domain;
 mod middlewares;
@@ -13,91 +15,365 @@ mod primitives;
 mod routing;
 mod util;
 use chrono::Utc;
-use primitives::http::request::Request;
+use middlewares::cors;
+use primitives::http::body::{BodyError, read_chunked};
+use primitives::http::head::{self, Head, HeadError};
+use primitives::http::method::Method;
+use primitives::http::request::{Request, Target, is_valid_request_id, request_id_header};
+use primitives::http::response::Response;
+use primitives::http::status::StatusCode;
+use primitives::http::stream::{Listener, Stream, tls_acceptor};
+use primitives::http::trace_context::TraceContext;
 use routing::{init, init_routes, route};
+use tracing::Instrument;
+use tracing::field::Empty;
+use util::metrics;
+use util::telemetry;
+use uuid::Uuid;
 
-async fn handle_connection(mut stream: TcpStream, _permit: tokio::sync::OwnedSemaphorePermit) {
-    let remote_addr = stream.peer_addr().ok();
-    let mut buf_reader = BufReader::new(&mut stream);
+async fn handle_connection(
+    stream: Stream,
+    remote_addr: Option<SocketAddr>,
+    tls: Option<TlsAcceptor>,
+    _permit: tokio::sync::OwnedSemaphorePermit,
+) {
+    let server = &config::get().server;
+    let stream = match (tls, stream) {
+        (Some(acceptor), Stream::Plain(stream)) => {
+            match timeout(server.keep_alive_timeout, acceptor.accept(stream)).await {
+                Ok(Ok(tls_stream)) => Stream::Tls(Box::new(tls_stream)),
+                _ => return,
+            }
+        }
+        (_, stream) => stream,
+    };
+    let mut stream = BufReader::new(stream);
+    let max_requests = server.keep_alive_max_requests;
+    let idle_timeout = server.keep_alive_timeout;
+    let mut served = 0usize;
+
+    loop {
+        // Wait for the first bytes of the next request; an idle or closed
+        // connection ends here
+        match timeout(idle_timeout, stream.fill_buf()).await {
+            Ok(Ok(buf)) if !buf.is_empty() => {}
+            _ => break,
+        }
+        served += 1;
+        match handle_request(stream, remote_addr, served < max_requests).await {
+            Some(next) => stream = next,
+            None => return,
+        }
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// Reads, routes and answers one request. Returns the stream when the
+/// connection stays open for another request.
+async fn handle_request(
+    mut stream: BufReader<Stream>,
+    remote_addr: Option<SocketAddr>,
+    keep_alive_allowed: bool,
+) -> Option<BufReader<Stream>> {
+    let server = &config::get().server;
     let mut http_request = Vec::new();
     let mut line = String::new();
 
     let timestamp = Utc::now();
+    // The whole request (line, headers and body) must arrive before this
+    // deadline, so a client dribbling bytes can't hold the task forever
+    let read_started = Instant::now();
+    let mut deadline = read_started + server.request_read_timeout;
 
-    while buf_reader.read_line(&mut line).await.unwrap() > 0 {
-        let trimmed = line.trim_end().to_string();
+    loop {
+        match timeout_at(deadline, stream.read_line(&mut line)).await {
+            Ok(Ok(n)) if n > 0 => {}
+            // Closed before the blank line: the head is incomplete
+            Ok(Ok(_)) => {
+                if http_request.is_empty() {
+                    return None;
+                }
+                let response = plain_response(400, &HeadError::Truncated.to_string());
+                return reject(stream, response).await;
+            }
+            // Not UTF-8, or the connection failed mid-line
+            Ok(Err(_)) => return reject(stream, plain_response(400, "Bad Request")).await,
+            Err(_) => return reject(stream, plain_response(408, "Request Timeout")).await,
+        }
+        let trimmed = line.trim_end_matches(['\r', '\n']).to_string();
         if trimmed.is_empty() {
+            // Stray CRLFs before a request line are ignored (RFC 9112 2.2)
+            if http_request.is_empty() {
+                line.clear();
+                continue;
+            }
             break;
         }
         http_request.push(trimmed);
         line.clear();
     }
 
-    let (method, url, _version) = if let Some(request_line) = http_request.get(0) {
-        let mut parts = request_line.split_whitespace();
-        (
-            parts.next().unwrap_or("").to_string(),
-            parts.next().unwrap_or("").to_string(),
-            parts.next().unwrap_or("").to_string(),
-        )
-    } else {
-        ("".to_string(), "".to_string(), "".to_string())
+    let Head {
+        method,
+        url,
+        version,
+        headers,
+    } = match head::parse(&http_request) {
+        Ok(head) => head,
+        Err(err) => return reject(stream, plain_response(400, &err.to_string())).await,
     };
 
-    let mut headers = HashMap::new();
-    for line in http_request.iter().skip(1) {
-        if let Some((key, value)) = line.split_once(": ") {
-            headers.insert(key.to_string(), value.to_string());
-        }
-    }
+    let Target {
+        path,
+        url,
+        query_params,
+        query_pairs,
+    } = Target::parse(&url);
 
-    let mut body = String::new();
-    if let Some(content_length) = headers.get("Content-Length") {
-        if let Ok(len) = content_length.parse::<usize>() {
-            let mut buf = vec![0u8; len];
-            buf_reader.read_exact(&mut buf).await.unwrap();
-            body = String::from_utf8_lossy(&buf).to_string();
-        }
+    let Ok(method) = method.parse::<Method>();
+
+    // A matched route may raise or lower the body limit and read timeout;
+    // the body hasn't been read yet, so both still apply to it
+    let matched = routing::matching_route(&method, &path);
+    let max_body_size = matched
+        .and_then(|r| r.max_body_size)
+        .unwrap_or(server.max_body_size);
+    if let Some(read_timeout) = matched.and_then(|r| r.read_timeout) {
+        deadline = read_started + read_timeout;
     }
 
-    // Build query_params from URL
-    let mut query_params = HashMap::new();
-    if let Some(idx) = url.find('?') {
-        let query = &url[idx + 1..];
-        for pair in query.split('&') {
-            let mut kv = pair.splitn(2, '=');
-            if let (Some(k), Some(v)) = (kv.next(), kv.next()) {
-                query_params.insert(k.to_string(), v.to_string());
+    let mut body = Vec::new();
+    let is_chunked = headers
+        .get("transfer-encoding")
+        .is_some_and(|v| v.to_ascii_lowercase().contains("chunked"));
+    if is_chunked {
+        match timeout_at(deadline, read_chunked(&mut stream, max_body_size)).await {
+            Ok(Ok(decoded)) => body = decoded,
+            Ok(Err(err)) => {
+                let response = match err {
+                    BodyError::TooLarge => plain_response(413, "Payload Too Large"),
+                    _ => plain_response(400, &err.to_string()),
+                };
+                return reject(stream, response).await;
             }
+            Err(_) => return reject(stream, plain_response(408, "Request Timeout")).await,
+        }
+    } else if let Some(content_length) = headers.get("content-length")
+        && let Ok(len) = content_length.parse::<usize>()
+    {
+        // Refuse oversized bodies before allocating or reading them
+        if len > max_body_size {
+            return reject(stream, plain_response(413, "Payload Too Large")).await;
+        }
+        body = vec![0u8; len];
+        match timeout_at(deadline, stream.read_exact(&mut body)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(_)) => return None,
+            Err(_) => return reject(stream, plain_response(408, "Request Timeout")).await,
         }
     }
 
+    // Reuse the caller's id so a request can be followed across services;
+    // empty, oversized or non-printable values are replaced rather than trusted
+    let request_id_header = request_id_header();
+    let request_id = headers
+        .get(&request_id_header.to_ascii_lowercase())
+        .map(|v| v.trim())
+        .filter(|v| is_valid_request_id(v))
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let trace = TraceContext::from_traceparent(headers.get("traceparent").map(|v| v.as_str()));
+
+    // The router fills in `route` once a pattern matches; DB calls made by
+    // handlers open child spans under this one
+    let span = tracing::info_span!(
+        "request",
+        method = %method,
+        route = Empty,
+        request_id = %request_id,
+        trace_id = %trace.trace_id,
+        span_id = %trace.span_id,
+        parent_id = trace.parent_id.as_deref().unwrap_or(""),
+        status = Empty,
+    );
+
     let mut request = Request {
         method,
         url,
+        version,
         headers,
         body,
         stream,
         remote_addr,
         timestamp,
         query_params,
+        query_pairs,
+        path_params: HashMap::new(),
+        request_id,
+        trace,
+        response_sent: false,
     };
 
-    let response = route(&mut request).await;
+    let started = Instant::now();
+    let in_flight = metrics::track_in_flight();
+    let serve_metrics_here = metrics::admin_port().is_none()
+        && request.method == Method::Get
+        && request.path() == metrics::path();
+    let mut response = match cors::preflight(&request) {
+        Some(response) => response,
+        None if serve_metrics_here => metrics_response(),
+        None => {
+            let mut response = route(&mut request).instrument(span.clone()).await;
+            cors::apply(&request, &mut response);
+            response
+        }
+    };
+    let elapsed = started.elapsed();
+    let duration_ms = elapsed.as_secs_f64() * 1000.0;
+    metrics::record_request(request.method_str(), response.status_code, elapsed);
+    span.record("status", response.status_code);
+    response.headers.insert(
+        "X-Response-Time".to_string(),
+        format!("{:.3}ms", duration_ms),
+    );
+    response
+        .headers
+        .insert(request_id_header, request.request_id.clone());
 
-    println!("//=====================//");
-    println!("{}", request);
+    let keep_alive = keep_alive_allowed
+        && !request.response_sent
+        && wants_keep_alive(&request.version, request.header("Connection"))
+        && !response
+            .headers
+            .get("Connection")
+            .is_some_and(|v| v.eq_ignore_ascii_case("close"));
+    if keep_alive {
+        response
+            .headers
+            .insert("Connection".to_string(), "keep-alive".to_string());
+    }
 
-    request
-        .stream
-        .write_all(&response.to_bytes())
-        .await
-        .unwrap();
-    let _ = request.stream.shutdown().await;
+    let written = if request.response_sent {
+        true
+    } else {
+        let bytes = response.to_bytes_for(request.header("Accept-Encoding"));
+        request.stream.write_all(&bytes).await.is_ok()
+    };
+    if !keep_alive || !written {
+        let _ = request.stream.shutdown().await;
+    }
+    drop(in_flight);
+
+    span.in_scope(|| {
+        if server.log_json {
+            telemetry::emit(&request.log_json(response.status_code, duration_ms));
+        } else {
+            telemetry::emit(&format!(
+                "//=====================//\n{}\nStatus: {} {} in {:.3}ms",
+                request,
+                response.status_code,
+                response
+                    .status()
+                    .map_or("Unknown", StatusCode::reason_phrase),
+                duration_ms
+            ));
+        }
+    });
+
+    (keep_alive && written).then_some(request.stream)
+}
+
+/// Answers with `response` and closes the connection; used when the request
+/// can't be read, so the stream is in an unknown state.
+async fn reject(mut stream: BufReader<Stream>, response: Response) -> Option<BufReader<Stream>> {
+    let _ = stream.write_all(&response.to_bytes()).await;
+    let _ = stream.shutdown().await;
+    None
+}
+
+/// HTTP/1.1 connections stay open unless the client sends `Connection: close`;
+/// HTTP/1.0 ones only when it asks for `Connection: keep-alive`.
+fn wants_keep_alive(version: &str, connection: Option<&str>) -> bool {
+    let has_token = |token: &str| {
+        connection.is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    };
+    if version.eq_ignore_ascii_case("HTTP/1.1") {
+        !has_token("close")
+    } else {
+        has_token("keep-alive")
+    }
+}
+
+fn plain_response(status_code: u16, body: &str) -> Response {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "text/plain".to_string());
+    Response {
+        status_code,
+        headers,
+        body: body.into(),
+    }
+}
+
+fn metrics_response() -> Response {
+    let mut headers = HashMap::new();
+    headers.insert(
+        "Content-Type".to_string(),
+        "text/plain; version=0.0.4".to_string(),
+    );
+    Response {
+        status_code: 200,
+        headers,
+        body: metrics::render().into(),
+    }
+}
+
+/// Admin listener for `METRICS_PORT`: answers `GET <METRICS_PATH>` with the
+/// metrics and everything else with 404, one request per connection.
+async fn serve_admin(listener: TcpListener) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            sleep(Duration::from_millis(50)).await;
+            continue;
+        };
+        tokio::spawn(async move {
+            let mut stream = BufReader::new(stream);
+            let mut request_line = String::new();
+            let mut line = String::new();
+            let read_head = async {
+                stream.read_line(&mut request_line).await.ok()?;
+                // Skip the headers; nothing in them matters here
+                while stream.read_line(&mut line).await.ok()? > 0 && !line.trim().is_empty() {
+                    line.clear();
+                }
+                Some(())
+            };
+            if timeout(config::get().server.keep_alive_timeout, read_head)
+                .await
+                .ok()
+                .flatten()
+                .is_none()
+            {
+                return;
+            }
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or("");
+            let path = parts.next().unwrap_or("").split('?').next().unwrap_or("");
+            let response = if method.eq_ignore_ascii_case("GET") && path == metrics::path() {
+                metrics_response()
+            } else {
+                plain_response(404, "Not Found")
+            };
+            let _ = stream.write_all(&response.to_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
 }
 
 fn main() {
     dotenv().ok();
+    // Fail fast on bad settings instead of at the first request that needs them
+    let config = config::init();
+    telemetry::init();
     // ANSI color codes
     const CYAN: &str = "\x1b[36m";
     const GREEN: &str = "\x1b[32m";
@@ -107,33 +383,31 @@ fn main() {
 
     init(init_routes());
 
-    let cores = env::var("CORES")
-        .ok()
-        .and_then(|v| v.parse::<usize>().ok())
-        .unwrap_or_else(|| {
-            std::thread::available_parallelism()
-                .map(|n| n.get())
-                .unwrap_or(1)
-        });
+    let cores = config.server.cores;
 
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let bind_addr = format!("127.0.0.1:{}", port);
+    // HTTPS when both a certificate and key are configured, plain HTTP otherwise
+    let tls = config
+        .server
+        .tls
+        .as_ref()
+        .map(|(cert, key)| tls_acceptor(cert, key).expect("Failed to load TLS certificate"));
 
     let max_connections = cores * 1024;
     let connection_limiter = std::sync::Arc::new(Semaphore::new(max_connections));
 
     // Verbose startup logging
     println!("{CYAN}Starting Base Rust Web API...{RESET}");
-    println!("{GREEN}Listening on port:{RESET} {YELLOW}{port}{RESET}");
     println!("{GREEN}Worker threads:{RESET} {YELLOW}{cores}{RESET}");
     println!("{GREEN}Max connections:{RESET} {YELLOW}{max_connections}{RESET}");
-    if let Ok(db_url) = env::var("DB_HOST") {
-        println!("{GREEN}DB Host:{RESET} {MAGENTA}{db_url}{RESET}");
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    println!("{GREEN}Protocol:{RESET} {YELLOW}{scheme}{RESET}");
+    if let Some(db_host) = &config.db.host {
+        println!("{GREEN}DB Host:{RESET} {MAGENTA}{db_host}{RESET}");
     }
-    if let Ok(db_name) = env::var("DB_NAME") {
+    if let Some(db_name) = &config.db.name {
         println!("{GREEN}DB Name:{RESET} {MAGENTA}{db_name}{RESET}");
     }
-    if let Ok(bcrypt_cost) = env::var("BCRYPT_COST") {
+    if let Some(bcrypt_cost) = config.bcrypt_cost {
         println!("{GREEN}Bcrypt cost:{RESET} {MAGENTA}{bcrypt_cost}{RESET}");
     } else {
         println!("{GREEN}Bcrypt cost:{RESET} {MAGENTA}default{RESET}");
@@ -141,8 +415,9 @@ fn main() {
 
     let mut senders = Vec::with_capacity(cores);
     for _ in 0..cores {
-        let (tx, mut rx) = mpsc::channel::<(TcpStream, tokio::sync::OwnedSemaphorePermit)>(1024);
+        let (tx, mut rx) = mpsc::channel::<Accepted>(1024);
         senders.push(tx);
+        let tls = tls.clone();
 
         std::thread::spawn(move || {
             let runtime = tokio::runtime::Builder::new_current_thread()
@@ -152,8 +427,13 @@ fn main() {
             let local = tokio::task::LocalSet::new();
 
             runtime.block_on(local.run_until(async move {
-                while let Some((stream, permit)) = rx.recv().await {
-                    tokio::task::spawn_local(handle_connection(stream, permit));
+                while let Some((stream, remote_addr, permit)) = rx.recv().await {
+                    tokio::task::spawn_local(handle_connection(
+                        stream,
+                        remote_addr,
+                        tls.clone(),
+                        permit,
+                    ));
                 }
             }));
         });
@@ -169,13 +449,36 @@ fn main() {
             .await
             .expect("Failed to initialize DB pool");
 
-        println!("{CYAN}Server is ready and accepting connections!{RESET}");
+        if let Some(metrics_port) = metrics::admin_port() {
+            let admin = TcpListener::bind(format!("127.0.0.1:{}", metrics_port))
+                .await
+                .expect("Failed to bind METRICS_PORT");
+            println!(
+                "{GREEN}Metrics:{RESET} {YELLOW}127.0.0.1:{metrics_port}{}{RESET}",
+                metrics::path()
+            );
+            tokio::spawn(serve_admin(admin));
+        }
 
-        let listener = TcpListener::bind(&bind_addr).await.unwrap();
+        let listener = match bind(&config.server).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("{YELLOW}Failed to start:{RESET} {err}");
+                std::process::exit(1);
+            }
+        };
+        println!("{GREEN}Listening on:{RESET} {YELLOW}{}{RESET}", bind_target(&config.server));
+        println!("{CYAN}Server is ready and accepting connections!{RESET}");
         let mut next = 0usize;
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
 
         loop {
-            let (stream, _) = match listener.accept().await {
+            let accepted = tokio::select! {
+                _ = &mut shutdown => break,
+                accepted = listener.accept() => accepted,
+            };
+            let (stream, remote_addr) = match accepted {
                 Ok(pair) => pair,
                 Err(err) => {
                     eprintln!("{YELLOW}Accept failed:{RESET} {err}");
@@ -186,7 +489,11 @@ fn main() {
 
             match connection_limiter.clone().try_acquire_owned() {
                 Ok(permit) => {
-                    if senders[next].send((stream, permit)).await.is_err() {
+                    if senders[next]
+                        .send((stream, remote_addr, permit))
+                        .await
+                        .is_err()
+                    {
                         eprintln!("{YELLOW}Worker channel closed{RESET}");
                     }
                 }
@@ -202,5 +509,233 @@ fn main() {
             }
             next = (next + 1) % senders.len();
         }
+
+        // Stop accepting, then wait for every held permit (one per in-flight
+        // connection) to come back before closing the pool
+        drop(listener);
+        if let Some(path) = &config.server.uds {
+            let _ = std::fs::remove_file(path);
+        }
+        println!("{CYAN}Shutdown signal received, draining connections...{RESET}");
+        let timeout = config.server.shutdown_timeout;
+        let started = std::time::Instant::now();
+        let mut last_logged = None;
+        loop {
+            let in_flight = max_connections - connection_limiter.available_permits();
+            if in_flight == 0 {
+                break;
+            }
+            if started.elapsed() >= timeout {
+                println!(
+                    "{YELLOW}Shutdown timeout reached with {in_flight} connection(s) still open{RESET}"
+                );
+                break;
+            }
+            if last_logged != Some(in_flight) {
+                println!("{GREEN}In-flight connections:{RESET} {YELLOW}{in_flight}{RESET}");
+                last_logged = Some(in_flight);
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        drop(senders);
+        db::close().await;
+        println!("{CYAN}Shutdown complete.{RESET}");
     });
 }
+
+/// A connection handed to a worker: the stream, its peer address (TCP
+/// only) and the permit counting it against the connection limit.
+type Accepted = (
+    Stream,
+    Option<SocketAddr>,
+    tokio::sync::OwnedSemaphorePermit,
+);
+
+/// Opens the listener for `SERVER_UDS`, or `SERVER_HOST`:`SERVER_PORT`, with
+/// the usual bind failures spelled out.
+async fn bind(server: &config::ServerConfig) -> Result<Listener, String> {
+    #[cfg(unix)]
+    if let Some(path) = &server.uds {
+        // A socket file left by a crashed run would make bind fail; only
+        // remove it when nothing is listening on it
+        if std::path::Path::new(path).exists() {
+            if tokio::net::UnixStream::connect(path).await.is_ok() {
+                return Err(format!("Unix socket {} is already in use", path));
+            }
+            std::fs::remove_file(path)
+                .map_err(|e| format!("Can't remove stale socket {}: {}", path, e))?;
+        }
+        return tokio::net::UnixListener::bind(path)
+            .map(Listener::Unix)
+            .map_err(|e| format!("Can't bind Unix socket {}: {}", path, e));
+    }
+
+    let addr = SocketAddr::new(server.host, server.port);
+    TcpListener::bind(addr)
+        .await
+        .map(Listener::Tcp)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AddrInUse => format!("{} is already in use", addr),
+            std::io::ErrorKind::AddrNotAvailable => {
+                format!("{} is not an address of this machine", server.host)
+            }
+            std::io::ErrorKind::PermissionDenied => {
+                format!("Permission denied binding {}", addr)
+            }
+            _ => format!("Can't bind {}: {}", addr, e),
+        })
+}
+
+/// What the startup banner shows: `unix:<path>` or `host:port`.
+fn bind_target(server: &config::ServerConfig) -> String {
+    match &server.uds {
+        Some(path) => format!("unix:{}", path),
+        None => SocketAddr::new(server.host, server.port).to_string(),
+    }
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM on Unix (what orchestrators send).
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn keep_alive_defaults_follow_the_protocol_version() {
+        assert!(wants_keep_alive("HTTP/1.1", None));
+        assert!(wants_keep_alive("HTTP/1.1", Some("Upgrade, Keep-Alive")));
+        assert!(!wants_keep_alive("HTTP/1.1", Some("close")));
+        assert!(!wants_keep_alive("HTTP/1.0", None));
+        assert!(wants_keep_alive("HTTP/1.0", Some("keep-alive")));
+    }
+
+    /// Writes `raw` to a fresh connection and returns everything the server
+    /// sends back before closing it.
+    #[cfg(unix)]
+    async fn exchange(raw: &str) -> String {
+        let (server, mut client) = tokio::net::UnixStream::pair().unwrap();
+        let permit = Arc::new(Semaphore::new(1)).acquire_owned().await.unwrap();
+        let connection = handle_connection(Stream::Unix(server), None, None, permit);
+        let client = async {
+            client.write_all(raw.as_bytes()).await.unwrap();
+            let mut received = String::new();
+            client.read_to_string(&mut received).await.unwrap();
+            received
+        };
+
+        let ((), received) = tokio::join!(connection, client);
+        received
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_several_requests_over_one_connection() {
+        let received = exchange(
+            "GET /first HTTP/1.1\r\nHost: test\r\n\r\n\
+             GET /second HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        assert_eq!(received.matches("HTTP/1.1 ").count(), 2);
+        assert!(received.contains("Connection: keep-alive"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn closes_http_1_0_connections_after_one_response() {
+        let received = exchange(
+            "GET /first HTTP/1.0\r\n\r\n\
+             GET /second HTTP/1.0\r\n\r\n",
+        )
+        .await;
+
+        assert_eq!(received.matches("HTTP/1.1 ").count(), 1);
+        assert!(!received.contains("Connection: keep-alive"));
+    }
+
+    #[tokio::test]
+    async fn serves_requests_over_tls() {
+        use rustls_pki_types::pem::PemObject;
+        use rustls_pki_types::{CertificateDer, ServerName};
+        use tokio_rustls::TlsConnector;
+        use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+        // Self-signed for `localhost`, valid until 2126
+        let cert = include_str!("../tests/fixtures/localhost.pem");
+        let key = include_str!("../tests/fixtures/localhost.key");
+        let dir = std::env::temp_dir().join(format!("tls_{}", Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert_path, cert).unwrap();
+        std::fs::write(&key_path, key).unwrap();
+        let acceptor = tls_acceptor(cert_path.to_str().unwrap(), key_path.to_str().unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        let acceptor = acceptor.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = async {
+            let (stream, remote_addr) = listener.accept().await.unwrap();
+            let permit = Arc::new(Semaphore::new(1)).acquire_owned().await.unwrap();
+            handle_connection(
+                Stream::Plain(stream),
+                Some(remote_addr),
+                Some(acceptor),
+                permit,
+            )
+            .await;
+        };
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_slice(cert.as_bytes()).unwrap())
+            .unwrap();
+        let client_config = ClientConfig::builder_with_provider(Arc::new(
+            tokio_rustls::rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let client = async {
+            let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let mut tls = TlsConnector::from(Arc::new(client_config))
+                .connect(ServerName::try_from("localhost").unwrap(), tcp)
+                .await
+                .unwrap();
+            tls.write_all(b"GET /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut received = String::new();
+            // Closing without close_notify is fine here; the body is complete
+            let _ = tls.read_to_string(&mut received).await;
+            received
+        };
+
+        let ((), received) = tokio::join!(server, client);
+
+        let (head, body) = received.split_once("\r\n\r\n").unwrap();
+        let mut lines = head.lines();
+        assert_eq!(lines.next(), Some("HTTP/1.1 404 Not Found"));
+        assert!(lines.any(|line| line == "Content-Type: application/problem+json"));
+        let problem: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(problem["status"], 404);
+    }
+}
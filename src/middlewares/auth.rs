@@ -0,0 +1,49 @@
+use crate::config;
+use crate::primitives::http::problem::Problem;
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::Response;
+use crate::primitives::http::status::StatusCode;
+use crate::routing::{Handler, RouteParams, next_handler};
+
+/// Rejects requests whose `Authorization: Bearer <token>` isn't one of the
+/// comma-separated `API_TOKENS`; the rest of the chain only runs for valid
+/// tokens. Attach with `middleware!(auth::require_bearer)`.
+#[allow(dead_code)]
+pub async fn require_bearer(
+    request: &mut Request,
+    params: &RouteParams,
+    handlers: &mut Vec<Handler>,
+) -> Response {
+    let Some(token) = request.bearer_token() else {
+        return unauthorized(request, "Missing bearer token");
+    };
+
+    let valid = config::get()
+        .api_tokens
+        .iter()
+        .any(|t| constant_time_eq(t.as_bytes(), token.as_bytes()));
+    if !valid {
+        return unauthorized(request, "Invalid bearer token");
+    }
+
+    next_handler(request, params, handlers).await
+}
+
+/// Compares without short-circuiting so response timing doesn't reveal how
+/// much of a token matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn unauthorized(request: &Request, detail: &str) -> Response {
+    let mut response = Problem::new(StatusCode::UNAUTHORIZED)
+        .detail(detail)
+        .to_response(request);
+    response
+        .headers
+        .insert("WWW-Authenticate".to_string(), "Bearer".to_string());
+    response
+}
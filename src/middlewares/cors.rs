@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::config;
+use crate::primitives::http::method::Method;
+use crate::primitives::http::problem::Problem;
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::{Response, add_vary};
+use crate::primitives::http::status::StatusCode;
+
+/// CORS settings read once from the environment. CORS is disabled when
+/// `CORS_ALLOWED_ORIGINS` is unset or empty.
+struct CorsConfig {
+    /// `None` means `*`
+    origins: Option<Vec<String>>,
+    methods: Vec<String>,
+    /// `None` means `*`: whatever the preflight asks for is allowed
+    headers: Option<Vec<String>>,
+    credentials: bool,
+    max_age: u64,
+}
+
+static CONFIG: OnceLock<Option<CorsConfig>> = OnceLock::new();
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+fn config() -> Option<&'static CorsConfig> {
+    CONFIG
+        .get_or_init(|| {
+            let settings = &config::get().cors;
+            let origins = settings.allowed_origins.as_deref()?;
+            let headers = settings.allowed_headers.trim();
+            Some(CorsConfig {
+                origins: (origins != "*").then(|| list(origins)),
+                methods: list(&settings.allowed_methods)
+                    .into_iter()
+                    .map(|m| m.to_ascii_uppercase())
+                    .collect(),
+                headers: (headers != "*").then(|| list(headers)),
+                credentials: settings.allow_credentials,
+                max_age: settings.max_age,
+            })
+        })
+        .as_ref()
+}
+
+impl CorsConfig {
+    /// The value for `Access-Control-Allow-Origin`, or `None` if the origin
+    /// isn't allowed. Credentialed mode can't use `*`, so the origin is
+    /// reflected instead.
+    fn allow_origin(&self, origin: &str) -> Option<String> {
+        match &self.origins {
+            None if self.credentials => Some(origin.to_string()),
+            None => Some("*".to_string()),
+            Some(origins) => origins
+                .iter()
+                .any(|o| o.eq_ignore_ascii_case(origin))
+                .then(|| origin.to_string()),
+        }
+    }
+
+    fn insert_origin_headers(&self, allow_origin: String, headers: &mut HashMap<String, String>) {
+        if allow_origin != "*" {
+            add_vary(headers, "Origin");
+        }
+        headers.insert("Access-Control-Allow-Origin".to_string(), allow_origin);
+        if self.credentials {
+            headers.insert(
+                "Access-Control-Allow-Credentials".to_string(),
+                "true".to_string(),
+            );
+        }
+    }
+}
+
+/// Answers an `OPTIONS` preflight directly: 204 with the `Access-Control-Allow-*`
+/// headers, or 403 when the origin or requested method isn't allowed. Returns
+/// `None` for anything that isn't a preflight so it goes through the router.
+pub fn preflight(request: &Request) -> Option<Response> {
+    let config = config()?;
+    if request.method != Method::Options {
+        return None;
+    }
+    let origin = request.header("Origin")?;
+    let requested_method = request.header("Access-Control-Request-Method")?;
+
+    let mut headers = HashMap::new();
+    let Some(allow_origin) = config.allow_origin(origin) else {
+        return Some(forbidden(request, "CORS origin not allowed"));
+    };
+    if !config
+        .methods
+        .iter()
+        .any(|m| m.eq_ignore_ascii_case(requested_method.trim()))
+    {
+        return Some(forbidden(request, "CORS method not allowed"));
+    }
+
+    config.insert_origin_headers(allow_origin, &mut headers);
+    headers.insert(
+        "Access-Control-Allow-Methods".to_string(),
+        config.methods.join(", "),
+    );
+    let allow_headers = match &config.headers {
+        Some(allowed) => allowed.join(", "),
+        None => request
+            .header("Access-Control-Request-Headers")
+            .unwrap_or("")
+            .to_string(),
+    };
+    if !allow_headers.is_empty() {
+        headers.insert("Access-Control-Allow-Headers".to_string(), allow_headers);
+    }
+    headers.insert(
+        "Access-Control-Max-Age".to_string(),
+        config.max_age.to_string(),
+    );
+
+    Some(Response {
+        status_code: 204,
+        headers,
+        body: Vec::new(),
+    })
+}
+
+/// Adds the CORS headers to a regular response when the request's `Origin`
+/// is allowed.
+pub fn apply(request: &Request, response: &mut Response) {
+    let Some(config) = config() else {
+        return;
+    };
+    let Some(origin) = request.header("Origin") else {
+        return;
+    };
+    if let Some(allow_origin) = config.allow_origin(origin) {
+        config.insert_origin_headers(allow_origin, &mut response.headers);
+    }
+}
+
+fn forbidden(request: &Request, detail: &str) -> Response {
+    Problem::new(StatusCode::FORBIDDEN)
+        .detail(detail)
+        .to_response(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origin_is_added_to_an_existing_vary() {
+        let cors = CorsConfig {
+            origins: Some(vec!["https://app.example".to_string()]),
+            methods: vec!["GET".to_string()],
+            headers: None,
+            credentials: false,
+            max_age: 0,
+        };
+        let mut headers = HashMap::new();
+        headers.insert("Vary".to_string(), "Accept".to_string());
+
+        cors.insert_origin_headers("https://app.example".to_string(), &mut headers);
+
+        assert_eq!(headers["Vary"], "Accept, Origin");
+        assert_eq!(
+            headers["Access-Control-Allow-Origin"],
+            "https://app.example"
+        );
+    }
+}
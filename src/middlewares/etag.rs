@@ -0,0 +1,69 @@
+use crate::primitives::http::etag;
+use crate::primitives::http::method::Method;
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::Response;
+use crate::routing::{Handler, RouteParams, next_handler};
+
+/// Conditional GET for a route's handler: runs the rest of the chain, then
+/// adds an `ETag` to a buffered `200` and answers `304 Not Modified` when
+/// the client's `If-None-Match` matches it. Attach it to read routes with
+/// `middleware!(etag::etag)`; the body is still built on every request, but
+/// an unchanged one isn't sent again. Streamed responses are left alone.
+pub async fn etag(
+    request: &mut Request,
+    params: &RouteParams,
+    handlers: &mut Vec<Handler>,
+) -> Response {
+    let mut response = next_handler(request, params, handlers).await;
+    let conditional = matches!(request.method, Method::Get | Method::Head);
+    if conditional && !request.response_sent {
+        etag::apply(request.header("If-None-Match"), &mut response);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{middleware, route};
+    use std::collections::HashMap;
+
+    async fn user(_request: &mut Request, _params: &RouteParams) -> Response {
+        Response {
+            status_code: 200,
+            headers: HashMap::new(),
+            body: b"{\"id\":1}".to_vec(),
+        }
+    }
+
+    async fn get(if_none_match: Option<&str>) -> Response {
+        let headers: Vec<(&str, &str)> = if_none_match
+            .map(|value| ("If-None-Match", value))
+            .into_iter()
+            .collect();
+        let mut request = Request::for_test(Method::Get, "/user/1", &headers);
+        let mut handlers = vec![route!(user), middleware!(etag)];
+        next_handler(&mut request, &RouteParams::default(), &mut handlers).await
+    }
+
+    #[tokio::test]
+    async fn matching_if_none_match_gets_304() {
+        let first = get(None).await;
+        let tag = first.headers["ETag"].clone();
+
+        let response = get(Some(&tag)).await;
+
+        assert_eq!(response.status_code, 304);
+        assert!(response.body.is_empty());
+        assert_eq!(response.headers["ETag"], tag);
+    }
+
+    #[tokio::test]
+    async fn other_if_none_match_gets_the_body() {
+        let response = get(Some("\"stale\", W/\"older\"")).await;
+
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, b"{\"id\":1}");
+        assert!(response.headers.contains_key("ETag"));
+    }
+}
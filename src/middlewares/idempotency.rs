@@ -0,0 +1,350 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::primitives::http::method::Method;
+use crate::primitives::http::problem::Problem;
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::Response;
+use crate::primitives::http::status::StatusCode;
+use crate::routing::{Handler, RouteParams, next_handler};
+
+/// Keys longer than this are rejected rather than stored.
+const MAX_KEY_LEN: usize = 255;
+
+/// How often expired entries are swept out.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+enum State {
+    /// The first request with this key is still being handled.
+    Pending,
+    Done(Response),
+}
+
+struct Entry {
+    /// Hash of the request body, so a key reused for a different request
+    /// is refused instead of answered with someone else's response.
+    fingerprint: [u8; 32],
+    state: State,
+    expires: Instant,
+}
+
+struct Store {
+    entries: HashMap<String, Entry>,
+    last_sweep: Instant,
+}
+
+static STORE: OnceLock<Mutex<Store>> = OnceLock::new();
+
+fn store() -> std::sync::MutexGuard<'static, Store> {
+    STORE
+        .get_or_init(|| Mutex::new(Store::new(Instant::now())))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+enum Claim {
+    /// No live entry: this request runs the handler.
+    Acquired,
+    Replay(Response),
+    InProgress,
+    Mismatch,
+    /// The store is full of requests that are still running.
+    Full,
+}
+
+impl Store {
+    fn new(now: Instant) -> Store {
+        Store {
+            entries: HashMap::new(),
+            last_sweep: now,
+        }
+    }
+
+    fn claim(
+        &mut self,
+        key: &str,
+        fingerprint: [u8; 32],
+        now: Instant,
+        ttl: Duration,
+        max_entries: usize,
+    ) -> Claim {
+        if let Some(entry) = self.entries.get(key).filter(|e| e.expires > now) {
+            return if entry.fingerprint != fingerprint {
+                Claim::Mismatch
+            } else {
+                match &entry.state {
+                    State::Pending => Claim::InProgress,
+                    State::Done(response) => Claim::Replay(response.clone()),
+                }
+            };
+        }
+
+        if !self.make_room(key, now, max_entries) {
+            return Claim::Full;
+        }
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                fingerprint,
+                state: State::Pending,
+                expires: now + ttl,
+            },
+        );
+        Claim::Acquired
+    }
+
+    /// Ensures inserting `key` keeps the store within `max_entries`: sweeps
+    /// expired entries (every `SWEEP_INTERVAL`, or straight away when full),
+    /// then evicts the oldest finished entry. Running requests are never
+    /// evicted, so returns false when they alone fill the store.
+    fn make_room(&mut self, key: &str, now: Instant, max_entries: usize) -> bool {
+        let full =
+            |store: &Store| store.entries.len() >= max_entries && !store.entries.contains_key(key);
+        if full(self) || now.duration_since(self.last_sweep) >= SWEEP_INTERVAL {
+            self.entries.retain(|_, entry| entry.expires > now);
+            self.last_sweep = now;
+        }
+        if !full(self) {
+            return true;
+        }
+
+        let oldest = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| matches!(entry.state, State::Done(_)))
+            .min_by_key(|(_, entry)| entry.expires)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(oldest) => {
+                self.entries.remove(&oldest);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn claim(key: &str, fingerprint: [u8; 32]) -> Claim {
+    let config = config::get();
+    store().claim(
+        key,
+        fingerprint,
+        Instant::now(),
+        config.idempotency_ttl,
+        config.idempotency_max_entries,
+    )
+}
+
+/// Releases a pending key if the handler never completes (e.g. the task is
+/// dropped), so the client can retry instead of getting 409 until expiry.
+struct PendingGuard {
+    key: Option<String>,
+}
+
+impl PendingGuard {
+    fn complete(mut self, response: Option<&Response>) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let mut store = store();
+        match response {
+            Some(response) => {
+                if let Some(entry) = store.entries.get_mut(&key) {
+                    entry.state = State::Done(response.clone());
+                }
+            }
+            None => {
+                store.entries.remove(&key);
+            }
+        }
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            store().entries.remove(&key);
+        }
+    }
+}
+
+/// The bearer token (hashed, so tokens aren't kept in memory) or else the
+/// client IP, so two clients can't read each other's responses by picking
+/// the same key.
+fn client_scope(request: &Request) -> String {
+    if let Some(token) = request.bearer_token() {
+        let digest = Sha256::digest(token.as_bytes());
+        let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        return format!("token:{}", hex);
+    }
+    match request.client_ip() {
+        Some(ip) => format!("ip:{}", ip),
+        None => "local".to_string(),
+    }
+}
+
+/// Makes retries of unsafe requests safe: the first request carrying an
+/// `Idempotency-Key` runs the rest of the chain and its response is stored
+/// for `IDEMPOTENCY_TTL_SECS`; a repeat with the same key, method, path and
+/// client gets that response back (with `Idempotent-Replayed: true`)
+/// without the handler running again. Attach it to routes that create or
+/// charge with `middleware!(idempotency::idempotency)`.
+///
+/// A repeat that arrives while the first is still running gets `409`, and a
+/// key reused with a different body gets `422`. 5xx responses and streamed
+/// ones aren't stored, so those can be retried for real. Requests without
+/// the header, and safe methods, pass straight through.
+///
+/// At most `IDEMPOTENCY_MAX_ENTRIES` keys are kept: the oldest stored
+/// response makes way for a new key, and if every slot is a request that is
+/// still running, the new one gets `503`.
+pub async fn idempotency(
+    request: &mut Request,
+    params: &RouteParams,
+    handlers: &mut Vec<Handler>,
+) -> Response {
+    let safe = matches!(request.method, Method::Get | Method::Head | Method::Options);
+    let Some(key) = request.header("Idempotency-Key").filter(|_| !safe) else {
+        return next_handler(request, params, handlers).await;
+    };
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Problem::new(StatusCode::BAD_REQUEST)
+            .detail(format!(
+                "Idempotency-Key must be 1 to {} characters",
+                MAX_KEY_LEN
+            ))
+            .to_response(request);
+    }
+
+    let scoped_key = format!(
+        "{} {} {} {}",
+        client_scope(request),
+        request.method,
+        request.path(),
+        key
+    );
+    let fingerprint: [u8; 32] = Sha256::digest(&request.body).into();
+
+    match claim(&scoped_key, fingerprint) {
+        Claim::Acquired => {}
+        Claim::Replay(mut response) => {
+            response
+                .headers
+                .insert("Idempotent-Replayed".to_string(), "true".to_string());
+            return response;
+        }
+        Claim::InProgress => {
+            return Problem::new(StatusCode::CONFLICT)
+                .detail("A request with this Idempotency-Key is still being processed")
+                .to_response(request);
+        }
+        Claim::Mismatch => {
+            return Problem::new(StatusCode::UNPROCESSABLE_ENTITY)
+                .detail("Idempotency-Key was already used with a different request body")
+                .to_response(request);
+        }
+        Claim::Full => {
+            return Problem::new(StatusCode::SERVICE_UNAVAILABLE)
+                .detail("Too many requests with an Idempotency-Key are in progress")
+                .to_response(request);
+        }
+    }
+
+    let guard = PendingGuard {
+        key: Some(scoped_key),
+    };
+    let response = next_handler(request, params, handlers).await;
+    let storable = !request.response_sent && response.status_code < 500;
+    guard.complete(storable.then_some(&response));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn finish(store: &mut Store, key: &str) {
+        let response = Response {
+            status_code: 201,
+            headers: HashMap::new(),
+            body: Vec::new(),
+        };
+        store.entries.get_mut(key).unwrap().state = State::Done(response);
+    }
+
+    #[test]
+    fn replays_a_finished_key() {
+        let now = Instant::now();
+        let mut store = Store::new(now);
+
+        assert!(matches!(
+            store.claim("a", [1; 32], now, TTL, 10),
+            Claim::Acquired
+        ));
+        assert!(matches!(
+            store.claim("a", [1; 32], now, TTL, 10),
+            Claim::InProgress
+        ));
+        finish(&mut store, "a");
+        assert!(matches!(
+            store.claim("a", [1; 32], now, TTL, 10),
+            Claim::Replay(_)
+        ));
+        assert!(matches!(
+            store.claim("a", [2; 32], now, TTL, 10),
+            Claim::Mismatch
+        ));
+    }
+
+    #[test]
+    fn evicts_the_oldest_finished_entry_when_full() {
+        let now = Instant::now();
+        let mut store = Store::new(now);
+        store.claim("old", [0; 32], now, TTL, 2);
+        finish(&mut store, "old");
+        store.claim("running", [0; 32], now + Duration::from_secs(1), TTL, 2);
+
+        let later = now + Duration::from_secs(2);
+        assert!(matches!(
+            store.claim("new", [0; 32], later, TTL, 2),
+            Claim::Acquired
+        ));
+
+        assert_eq!(store.entries.len(), 2);
+        assert!(!store.entries.contains_key("old"));
+        assert!(store.entries.contains_key("running"));
+    }
+
+    #[test]
+    fn rejects_new_keys_when_full_of_running_requests() {
+        let now = Instant::now();
+        let mut store = Store::new(now);
+        store.claim("a", [0; 32], now, TTL, 2);
+        store.claim("b", [0; 32], now, TTL, 2);
+
+        assert!(matches!(
+            store.claim("c", [0; 32], now, TTL, 2),
+            Claim::Full
+        ));
+        assert_eq!(store.entries.len(), 2);
+    }
+
+    #[test]
+    fn sweeps_expired_entries_on_insert() {
+        let now = Instant::now();
+        let mut store = Store::new(now);
+        store.claim("a", [0; 32], now, Duration::from_secs(1), 100);
+        store.claim("b", [0; 32], now, Duration::from_secs(1), 100);
+
+        let later = now + SWEEP_INTERVAL;
+        store.claim("c", [0; 32], later, TTL, 100);
+
+        assert_eq!(store.entries.len(), 1);
+        assert!(store.entries.contains_key("c"));
+    }
+}
@@ -0,0 +1,5 @@
+pub mod auth;
+pub mod cors;
+pub mod etag;
+pub mod idempotency;
+pub mod rate_limit;
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::primitives::http::problem::Problem;
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::Response;
+use crate::primitives::http::status::StatusCode;
+use crate::routing::{Handler, RouteParams, next_handler};
+
+/// Token bucket settings, read once from the environment. Each client gets
+/// `burst` tokens, refilled at `requests` per `window`.
+struct RateLimitConfig {
+    requests: f64,
+    window: Duration,
+    burst: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Limiter {
+    buckets: HashMap<IpAddr, Bucket>,
+    last_sweep: Instant,
+}
+
+static CONFIG: OnceLock<RateLimitConfig> = OnceLock::new();
+static LIMITER: OnceLock<Mutex<Limiter>> = OnceLock::new();
+
+fn config() -> &'static RateLimitConfig {
+    CONFIG.get_or_init(|| {
+        let settings = &config::get().rate_limit;
+        RateLimitConfig {
+            requests: settings.requests as f64,
+            window: settings.window,
+            burst: settings.burst as f64,
+        }
+    })
+}
+
+impl RateLimitConfig {
+    fn refill_per_sec(&self) -> f64 {
+        self.requests / self.window.as_secs_f64()
+    }
+
+    /// How long an untouched bucket takes to fill back up; after that it is
+    /// indistinguishable from a new one and can be dropped.
+    fn idle_after(&self) -> Duration {
+        Duration::from_secs_f64(self.burst / self.refill_per_sec())
+    }
+}
+
+/// Takes a token for `ip`, or returns how long until one is available.
+fn take(ip: IpAddr) -> Result<(), Duration> {
+    let config = config();
+    let now = Instant::now();
+    let mut limiter = LIMITER
+        .get_or_init(|| {
+            Mutex::new(Limiter {
+                buckets: HashMap::new(),
+                last_sweep: now,
+            })
+        })
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    // Evict idle clients once per window so the map doesn't grow forever
+    if now.duration_since(limiter.last_sweep) >= config.window {
+        let idle_after = config.idle_after();
+        limiter
+            .buckets
+            .retain(|_, bucket| now.duration_since(bucket.updated) < idle_after);
+        limiter.last_sweep = now;
+    }
+
+    let bucket = limiter.buckets.entry(ip).or_insert(Bucket {
+        tokens: config.burst,
+        updated: now,
+    });
+    let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * config.refill_per_sec()).min(config.burst);
+    bucket.updated = now;
+
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        Ok(())
+    } else {
+        let missing = 1.0 - bucket.tokens;
+        Err(Duration::from_secs_f64(missing / config.refill_per_sec()))
+    }
+}
+
+/// Per-client token bucket keyed by `client_ip()`. Attach it to sensitive
+/// routes with `middleware!(rate_limit::rate_limit)`; over-limit requests get
+/// `429 Too Many Requests` with `Retry-After`.
+pub async fn rate_limit(
+    request: &mut Request,
+    params: &RouteParams,
+    handlers: &mut Vec<Handler>,
+) -> Response {
+    let Some(ip) = request.client_ip() else {
+        return next_handler(request, params, handlers).await;
+    };
+
+    match take(ip) {
+        Ok(()) => next_handler(request, params, handlers).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = Problem::new(StatusCode::TOO_MANY_REQUESTS)
+                .detail(format!("Rate limit exceeded; retry in {}s", retry_after))
+                .to_response(request);
+            response
+                .headers
+                .insert("Retry-After".to_string(), retry_after.to_string());
+            response
+        }
+    }
+}
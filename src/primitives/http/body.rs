@@ -0,0 +1,123 @@
+use std::fmt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+#[derive(Debug)]
+pub enum BodyError {
+    TooLarge,
+    Malformed(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::TooLarge => write!(f, "request body exceeds the configured limit"),
+            BodyError::Malformed(reason) => write!(f, "malformed request body: {}", reason),
+            BodyError::Io(err) => write!(f, "failed to read request body: {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for BodyError {
+    fn from(err: std::io::Error) -> Self {
+        BodyError::Io(err)
+    }
+}
+
+/// Decodes a `Transfer-Encoding: chunked` body: hex size lines, each chunk
+/// followed by CRLF, ending at a zero-size chunk. Chunk extensions and
+/// trailer headers are read and discarded. Aborts once `max_size` is crossed.
+pub async fn read_chunked<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_size: usize,
+) -> Result<Vec<u8>, BodyError> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(BodyError::Malformed(
+                "unexpected end of chunked body".into(),
+            ));
+        }
+        let size_field = line.trim_end().split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_field, 16)
+            .map_err(|_| BodyError::Malformed(format!("invalid chunk size '{}'", size_field)))?;
+
+        if size == 0 {
+            break;
+        }
+        if size > max_size - body.len() {
+            return Err(BodyError::TooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).await?;
+
+        let mut crlf = [0u8; 2];
+        reader.read_exact(&mut crlf).await?;
+        if &crlf != b"\r\n" {
+            return Err(BodyError::Malformed(
+                "chunk is not terminated by CRLF".into(),
+            ));
+        }
+    }
+
+    // Trailer headers, terminated by an empty line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reassembles_chunks_and_skips_trailers() {
+        let raw = b"5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nExpires: never\r\n\r\nGET /next";
+        let mut reader = &raw[..];
+
+        let body = read_chunked(&mut reader, 1024).await.unwrap();
+
+        assert_eq!(body, b"hello, world");
+        // The next pipelined request is left unread
+        assert_eq!(reader, b"GET /next");
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_size_limit() {
+        let mut reader = &b"5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n"[..];
+
+        let result = read_chunked(&mut reader, 8).await;
+
+        assert!(matches!(result, Err(BodyError::TooLarge)));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_chunks() {
+        let mut bad_size = &b"zz\r\nhello\r\n0\r\n\r\n"[..];
+        let mut missing_crlf = &b"5\r\nhelloX0\r\n\r\n"[..];
+        let mut cut_short = &b"5\r\nhello\r\n"[..];
+
+        assert!(matches!(
+            read_chunked(&mut bad_size, 1024).await,
+            Err(BodyError::Malformed(_))
+        ));
+        assert!(matches!(
+            read_chunked(&mut missing_crlf, 1024).await,
+            Err(BodyError::Malformed(_))
+        ));
+        assert!(matches!(
+            read_chunked(&mut cut_short, 1024).await,
+            Err(BodyError::Malformed(_))
+        ));
+    }
+}
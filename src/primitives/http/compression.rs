@@ -0,0 +1,184 @@
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use std::io::Write;
+
+use crate::config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Encoding> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+}
+
+/// Bodies smaller than this are sent as-is (`COMPRESSION_MIN_SIZE`).
+fn min_size() -> usize {
+    config::get().compression.min_size
+}
+
+/// Encodings the server may use, in order of preference
+/// (`COMPRESSION_ENCODINGS`, e.g. `gzip,deflate`; empty disables compression).
+fn enabled_encodings() -> Vec<Encoding> {
+    config::get()
+        .compression
+        .encodings
+        .iter()
+        .filter_map(|name| Encoding::parse(name))
+        .collect()
+}
+
+/// Content types that are already compressed and gain nothing from gzip.
+fn is_precompressed(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    (essence.starts_with("image/") && essence != "image/svg+xml")
+        || essence.starts_with("audio/")
+        || essence.starts_with("video/")
+        || matches!(
+            essence.as_str(),
+            "application/zip"
+                | "application/gzip"
+                | "application/x-gzip"
+                | "application/x-7z-compressed"
+                | "application/x-rar-compressed"
+                | "application/pdf"
+                | "font/woff"
+                | "font/woff2"
+        )
+}
+
+/// Picks the first enabled encoding the client accepts. `q=0` entries are
+/// refused, and `*` accepts anything not listed explicitly.
+fn negotiate(accept_encoding: &str, enabled: &[Encoding]) -> Option<Encoding> {
+    let mut accepted = Vec::new();
+    let mut refused = Vec::new();
+    let mut wildcard = None;
+
+    for entry in accept_encoding.split(',') {
+        let mut fields = entry.split(';');
+        let name = fields.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = fields
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let ok = quality > 0.0;
+
+        if name == "*" {
+            wildcard = Some(ok);
+        } else if let Some(encoding) = Encoding::parse(&name) {
+            if ok {
+                accepted.push(encoding);
+            } else {
+                refused.push(encoding);
+            }
+        }
+    }
+
+    enabled
+        .iter()
+        .copied()
+        .find(|enc| accepted.contains(enc) || (wildcard == Some(true) && !refused.contains(enc)))
+}
+
+/// Picks an encoding for a streamed body, whose length isn't known up front,
+/// so `COMPRESSION_MIN_SIZE` doesn't apply.
+pub fn choose_for_stream(
+    accept_encoding: Option<&str>,
+    content_type: Option<&str>,
+) -> Option<Encoding> {
+    let accept_encoding = accept_encoding?;
+    if content_type.is_some_and(is_precompressed) {
+        return None;
+    }
+    negotiate(accept_encoding, &enabled_encodings())
+}
+
+/// Decides whether a response body should be compressed and with what.
+pub fn choose(
+    accept_encoding: Option<&str>,
+    content_type: Option<&str>,
+    body_len: usize,
+) -> Option<Encoding> {
+    let accept_encoding = accept_encoding?;
+    if body_len < min_size() || content_type.is_some_and(is_precompressed) {
+        return None;
+    }
+    negotiate(accept_encoding, &enabled_encodings())
+}
+
+pub fn compress(body: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// Incremental compressor for streamed bodies: each `encode` returns the
+/// compressed bytes produced so far, so nothing is held beyond the encoder's
+/// own window.
+pub enum StreamEncoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+impl StreamEncoder {
+    pub fn new(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Gzip => {
+                StreamEncoder::Gzip(GzEncoder::new(Vec::new(), Compression::default()))
+            }
+            Encoding::Deflate => {
+                StreamEncoder::Deflate(ZlibEncoder::new(Vec::new(), Compression::default()))
+            }
+        }
+    }
+
+    pub fn encode(&mut self, chunk: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            StreamEncoder::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            StreamEncoder::Deflate(encoder) => {
+                encoder.write_all(chunk)?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+        }
+    }
+
+    /// The remaining compressed bytes, including the format's trailer.
+    pub fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            StreamEncoder::Gzip(encoder) => encoder.finish(),
+            StreamEncoder::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
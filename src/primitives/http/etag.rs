@@ -0,0 +1,84 @@
+use sha2::{Digest, Sha256};
+
+use super::response::Response;
+
+/// Strong validator built from a SHA-256 of the body, e.g. `"3f2a...9c"`.
+pub fn generate(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// Whether an `If-None-Match` value (`*` or a comma-separated list of tags)
+/// matches `etag`. Uses weak comparison, so `W/"x"` matches `"x"`.
+pub fn matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Opt-in conditional GET for a buffered `200` response: sets `ETag` (unless
+/// the handler already did) and turns the response into a bodiless
+/// `304 Not Modified` when `If-None-Match` matches it.
+pub fn apply(if_none_match: Option<&str>, response: &mut Response) {
+    if response.status_code != 200 {
+        return;
+    }
+    let etag = response
+        .headers
+        .entry("ETag".to_string())
+        .or_insert_with(|| generate(&response.body))
+        .clone();
+    if if_none_match.is_some_and(|value| matches(value, &etag)) {
+        response.status_code = 304;
+        response.body = Vec::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn ok(body: &[u8]) -> Response {
+        Response {
+            status_code: 200,
+            headers: HashMap::new(),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn matching_tag_turns_into_304() {
+        let tag = generate(b"hello");
+        let mut response = ok(b"hello");
+
+        apply(Some(&format!("\"other\", {}", tag)), &mut response);
+
+        assert_eq!(response.status_code, 304);
+        assert!(response.body.is_empty());
+        assert_eq!(response.headers["ETag"], tag);
+    }
+
+    #[test]
+    fn non_matching_tag_keeps_the_body() {
+        let mut response = ok(b"hello");
+
+        apply(Some(&generate(b"goodbye")), &mut response);
+
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, b"hello");
+        assert_eq!(response.headers["ETag"], generate(b"hello"));
+    }
+
+    #[test]
+    fn weak_and_wildcard_tags_match() {
+        let tag = generate(b"hello");
+
+        assert!(matches(&format!("W/{}", tag), &tag));
+        assert!(matches("*", &tag));
+        assert!(!matches("", &tag));
+    }
+}
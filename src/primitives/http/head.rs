@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// The request line and headers, validated but otherwise uninterpreted.
+#[derive(Debug)]
+pub struct Head {
+    pub method: String,
+    pub url: String,
+    pub version: String,
+    /// Lowercased names; a repeated header keeps its last value.
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub enum HeadError {
+    /// The connection closed before the blank line ending the headers.
+    Truncated,
+    MalformedRequestLine(String),
+    UnsupportedVersion(String),
+    MalformedHeader(String),
+}
+
+impl fmt::Display for HeadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadError::Truncated => write!(f, "request ended before the end of the headers"),
+            HeadError::MalformedRequestLine(line) => {
+                write!(f, "malformed request line: {}", line)
+            }
+            HeadError::UnsupportedVersion(version) => {
+                write!(f, "unsupported HTTP version: {}", version)
+            }
+            HeadError::MalformedHeader(line) => write!(f, "malformed header line: {}", line),
+        }
+    }
+}
+
+/// Parses `METHOD target HTTP/1.x` followed by `Name: value` lines (already
+/// split, without line endings). Anything else is an error to answer with
+/// 400 rather than a request to route.
+pub fn parse(lines: &[String]) -> Result<Head, HeadError> {
+    let Some(request_line) = lines.first() else {
+        return Err(HeadError::Truncated);
+    };
+    let parts: Vec<&str> = request_line.split(' ').collect();
+    let [method, url, version] = parts[..] else {
+        return Err(HeadError::MalformedRequestLine(printable(request_line)));
+    };
+    if !is_token(method) || url.is_empty() || !version.starts_with("HTTP/") {
+        return Err(HeadError::MalformedRequestLine(printable(request_line)));
+    }
+    if version != "HTTP/1.1" && version != "HTTP/1.0" {
+        return Err(HeadError::UnsupportedVersion(printable(version)));
+    }
+
+    let mut headers = HashMap::new();
+    for line in &lines[1..] {
+        // Continuation lines (obsolete folding) and whitespace before the
+        // colon are both rejected, as RFC 9112 requires
+        let parsed = line.split_once(':').filter(|(name, _)| is_token(name));
+        let Some((name, value)) = parsed else {
+            return Err(HeadError::MalformedHeader(printable(line)));
+        };
+        headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    Ok(Head {
+        method: method.to_string(),
+        url: url.to_string(),
+        version: version.to_string(),
+        headers,
+    })
+}
+
+/// RFC 9110 `token`: what method and header names are made of.
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Clips and escapes client input before it is echoed in an error body.
+fn printable(value: &str) -> String {
+    let clipped: String = value.chars().take(100).collect();
+    clipped.escape_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_a_request() {
+        let head = parse(&lines(&["GET /users?page=2 HTTP/1.1", "Host: example.com"])).unwrap();
+
+        assert_eq!(head.method, "GET");
+        assert_eq!(head.url, "/users?page=2");
+        assert_eq!(head.version, "HTTP/1.1");
+        assert_eq!(head.headers["host"], "example.com");
+    }
+
+    #[test]
+    fn rejects_a_truncated_request() {
+        assert!(matches!(parse(&[]), Err(HeadError::Truncated)));
+        assert!(matches!(
+            parse(&lines(&["GET /users"])),
+            Err(HeadError::MalformedRequestLine(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_a_missing_method() {
+        assert!(matches!(
+            parse(&lines(&["/users HTTP/1.1"])),
+            Err(HeadError::MalformedRequestLine(_))
+        ));
+        assert!(matches!(
+            parse(&lines(&[" /users HTTP/1.1"])),
+            Err(HeadError::MalformedRequestLine(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_a_header_without_a_colon() {
+        let result = parse(&lines(&["GET / HTTP/1.1", "Host example.com"]));
+
+        assert!(
+            matches!(result, Err(HeadError::MalformedHeader(line)) if line == "Host example.com")
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        assert!(matches!(
+            parse(&lines(&["GET / HTTP/2.0"])),
+            Err(HeadError::UnsupportedVersion(_))
+        ));
+    }
+}
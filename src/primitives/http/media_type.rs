@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// A parsed `Content-Type` value such as `application/json; charset=utf-8`.
+/// Type, subtype and parameter names are lowercased; parameter values keep
+/// their case (multipart boundaries are case-sensitive), except `charset`.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType {
+    pub kind: String,
+    pub subtype: String,
+    pub charset: Option<String>,
+    /// Every parameter other than `charset`.
+    pub params: HashMap<String, String>,
+}
+
+#[allow(dead_code)]
+impl MediaType {
+    pub fn parse(value: &str) -> Option<MediaType> {
+        let (essence, rest) = match value.find(';') {
+            Some(idx) => (&value[..idx], &value[idx + 1..]),
+            None => (value, ""),
+        };
+        let (kind, subtype) = essence.trim().split_once('/')?;
+        let (kind, subtype) = (kind.trim(), subtype.trim());
+        if kind.is_empty() || subtype.is_empty() {
+            return None;
+        }
+
+        let mut charset = None;
+        let mut params = HashMap::new();
+        for (name, value) in parse_params(rest) {
+            if name == "charset" {
+                charset = Some(value.to_ascii_lowercase());
+            } else {
+                params.insert(name, value);
+            }
+        }
+
+        Some(MediaType {
+            kind: kind.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            charset,
+            params,
+        })
+    }
+
+    /// The bare `type/subtype`, e.g. `application/json`.
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.kind, self.subtype)
+    }
+
+    /// Case-insensitive comparison against a bare `type/subtype`.
+    pub fn is(&self, essence: &str) -> bool {
+        essence.split_once('/').is_some_and(|(kind, subtype)| {
+            self.kind.eq_ignore_ascii_case(kind.trim())
+                && self.subtype.eq_ignore_ascii_case(subtype.trim())
+        })
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        if name.eq_ignore_ascii_case("charset") {
+            return self.charset.as_deref();
+        }
+        self.params
+            .get(&name.to_ascii_lowercase())
+            .map(|v| v.as_str())
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.kind, self.subtype)?;
+        if let Some(charset) = &self.charset {
+            write!(f, "; charset={}", charset)?;
+        }
+        for (name, value) in &self.params {
+            write!(f, "; {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `; name=value; name="quoted; value"` into pairs. Quoted values may
+/// contain `;` and backslash-escaped characters.
+fn parse_params(input: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c == ';' {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        let name = name.trim().to_ascii_lowercase();
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            while chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+                chars.next();
+            }
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        _ => value.push(c),
+                    }
+                }
+                // Drop anything between the closing quote and the next `;`
+                while chars.peek().is_some_and(|&c| c != ';') {
+                    chars.next();
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c == ';' {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                value = value.trim().to_string();
+            }
+        }
+
+        if !name.is_empty() && !value.is_empty() {
+            params.push((name, value));
+        }
+        if chars.next().is_none() {
+            break;
+        }
+    }
+
+    params
+}
@@ -1,2 +1,17 @@
+pub mod body;
+pub mod compression;
+pub mod etag;
+pub mod head;
+pub mod media_type;
+pub mod method;
+pub mod multipart;
+pub mod problem;
+pub mod range;
 pub mod request;
-pub mod response;
\ No newline at end of file
+pub mod response;
+pub mod sse;
+pub mod static_files;
+pub mod status;
+pub mod stream;
+pub mod streaming;
+pub mod trace_context;
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+/// One part of a `multipart/form-data` body.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+#[allow(dead_code)]
+impl Part {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+}
+
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let next_delimiter = format!("\r\n--{}", boundary).into_bytes();
+
+    let mut pos = find(body, &delimiter, 0)
+        .ok_or_else(|| "Multipart body does not contain the boundary".to_string())?
+        + delimiter.len();
+    let mut parts = Vec::new();
+
+    loop {
+        if body[pos..].starts_with(b"--") {
+            break;
+        }
+        if body[pos..].starts_with(b"\r\n") {
+            pos += 2;
+        }
+
+        let headers_end = find(body, b"\r\n\r\n", pos)
+            .ok_or_else(|| "Multipart part is missing its header terminator".to_string())?;
+        let headers = parse_headers(&String::from_utf8_lossy(&body[pos..headers_end]));
+        let data_start = headers_end + 4;
+        let data_end = find(body, &next_delimiter, data_start)
+            .ok_or_else(|| "Multipart body is missing its closing boundary".to_string())?;
+
+        let disposition = headers
+            .get("content-disposition")
+            .ok_or_else(|| "Multipart part is missing Content-Disposition".to_string())?;
+        let params = disposition_params(disposition);
+        let name = params
+            .get("name")
+            .cloned()
+            .ok_or_else(|| "Multipart part is missing a name".to_string())?;
+
+        parts.push(Part {
+            name,
+            filename: params.get("filename").cloned(),
+            content_type: headers.get("content-type").cloned(),
+            data: body[data_start..data_end].to_vec(),
+        });
+
+        pos = data_end + next_delimiter.len();
+        if pos >= body.len() {
+            break;
+        }
+    }
+
+    Ok(parts)
+}
+
+fn parse_headers(raw: &str) -> HashMap<String, String> {
+    raw.split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect()
+}
+
+fn disposition_params(value: &str) -> HashMap<String, String> {
+    value
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .map(|(k, v)| {
+            (
+                k.trim().to_ascii_lowercase(),
+                v.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from > haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_text_field_and_a_file() {
+        let file: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x00, 0xff];
+        let mut body = Vec::new();
+        body.extend_from_slice(b"--XyZ\r\n");
+        body.extend_from_slice(b"Content-Disposition: form-data; name=\"title\"\r\n\r\n");
+        body.extend_from_slice(b"Holiday photo\r\n");
+        body.extend_from_slice(b"--XyZ\r\n");
+        body.extend_from_slice(
+            b"Content-Disposition: form-data; name=\"photo\"; filename=\"beach.png\"\r\n",
+        );
+        body.extend_from_slice(b"Content-Type: image/png\r\n\r\n");
+        body.extend_from_slice(file);
+        body.extend_from_slice(b"\r\n--XyZ--\r\n");
+
+        let parts = parse(&body, "XyZ").unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "title");
+        assert_eq!(parts[0].filename, None);
+        assert_eq!(parts[0].content_type, None);
+        assert_eq!(parts[0].text(), "Holiday photo");
+        assert_eq!(parts[1].name, "photo");
+        assert_eq!(parts[1].filename.as_deref(), Some("beach.png"));
+        assert_eq!(parts[1].content_type.as_deref(), Some("image/png"));
+        assert_eq!(parts[1].data, file);
+    }
+
+    #[test]
+    fn rejects_a_body_without_the_boundary() {
+        assert!(parse(b"no parts here", "XyZ").is_err());
+        assert!(
+            parse(
+                b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nunterminated",
+                "XyZ"
+            )
+            .is_err()
+        );
+    }
+}
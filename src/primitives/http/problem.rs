@@ -0,0 +1,168 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::request::Request;
+use super::response::Response;
+use super::status::StatusCode;
+
+/// An error description in the shape of RFC 7807 (`type`, `title`, `status`,
+/// `detail`), rendered as JSON, HTML or plain text depending on what the
+/// client's `Accept` header prefers.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    /// URI identifying the kind of problem; `about:blank` means the status
+    /// code says it all.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[allow(dead_code)]
+impl Problem {
+    /// A problem titled with the status's reason phrase.
+    pub fn new(status: StatusCode) -> Self {
+        Problem {
+            problem_type: "about:blank".to_string(),
+            title: status.reason_phrase().to_string(),
+            status: status.as_u16(),
+            detail: None,
+        }
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn problem_type(mut self, uri: impl Into<String>) -> Self {
+        self.problem_type = uri.into();
+        self
+    }
+
+    /// Renders for the request's `Accept` header.
+    pub fn to_response(&self, request: &Request) -> Response {
+        self.render(request.header("Accept"))
+    }
+
+    /// JSON (`application/problem+json`) unless the client prefers HTML or
+    /// plain text; a missing `Accept` or `*/*` also gets JSON.
+    pub fn render(&self, accept: Option<&str>) -> Response {
+        let (content_type, body) = match negotiate(accept) {
+            Format::Json => (
+                "application/problem+json",
+                serde_json::to_string(self).unwrap_or_default(),
+            ),
+            Format::Html => ("text/html; charset=utf-8", self.to_html()),
+            Format::Text => ("text/plain; charset=utf-8", self.to_text()),
+        };
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), content_type.to_string());
+        headers.insert("Vary".to_string(), "Accept".to_string());
+        Response {
+            status_code: self.status,
+            headers,
+            body: body.into(),
+        }
+    }
+
+    fn heading(&self) -> String {
+        format!("{} {}", self.status, self.title)
+    }
+
+    fn to_text(&self) -> String {
+        match &self.detail {
+            Some(detail) => format!("{}\n{}\n", self.heading(), detail),
+            None => format!("{}\n", self.heading()),
+        }
+    }
+
+    fn to_html(&self) -> String {
+        let heading = escape_html(&self.heading());
+        let detail = self
+            .detail
+            .as_deref()
+            .map(|d| format!("<p>{}</p>", escape_html(d)))
+            .unwrap_or_default();
+        format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head>\
+             <body><h1>{0}</h1>{1}</body></html>\n",
+            heading, detail
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Html,
+    Text,
+}
+
+/// Picks the format with the highest `q`, each weighted by the most
+/// specific `Accept` entry matching it (`text/html` over `text/*` over
+/// `*/*`). Ties go to JSON, then HTML; if everything is refused, JSON is
+/// still sent rather than a 406 for an error.
+fn negotiate(accept: Option<&str>) -> Format {
+    let Some(accept) = accept.filter(|a| !a.trim().is_empty()) else {
+        return Format::Json;
+    };
+
+    let entries: Vec<(String, f32)> = accept
+        .split(',')
+        .map(|entry| {
+            let mut fields = entry.split(';');
+            let range = fields.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = fields
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (range, quality)
+        })
+        .collect();
+
+    let candidates = [
+        (
+            Format::Json,
+            &["application/problem+json", "application/json"][..],
+        ),
+        (Format::Html, &["text/html"][..]),
+        (Format::Text, &["text/plain"][..]),
+    ];
+    let mut best = (Format::Json, 0.0f32);
+    for (format, media_types) in candidates {
+        let quality = media_types
+            .iter()
+            .filter_map(|media_type| quality_for(&entries, media_type))
+            .fold(0.0f32, f32::max);
+        if quality > best.1 {
+            best = (format, quality);
+        }
+    }
+    best.0
+}
+
+/// `q` of the most specific entry matching `media_type`, if any does.
+fn quality_for(entries: &[(String, f32)], media_type: &str) -> Option<f32> {
+    let (kind, _) = media_type.split_once('/')?;
+    let type_wildcard = format!("{}/*", kind);
+    [media_type, type_wildcard.as_str(), "*/*"]
+        .iter()
+        .find_map(|range| entries.iter().find(|(r, _)| r == range).map(|(_, q)| *q))
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,150 @@
+use super::response::Response;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable `Range` header; send the whole body
+    Full,
+    /// Inclusive start and end offsets
+    Partial(usize, usize),
+    Unsatisfiable,
+}
+
+/// Parses a single `bytes=start-end`, `bytes=start-` or `bytes=-suffix` range
+/// against a body of `len` bytes. Malformed headers and multi-range requests
+/// fall back to `Full`.
+pub fn parse(header: &str, len: usize) -> ByteRange {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // Suffix range: the last `end` bytes
+        return match end.parse::<usize>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(len.saturating_sub(suffix), len - 1),
+            Err(_) => ByteRange::Full,
+        };
+    }
+
+    let Ok(start) = start.parse::<usize>() else {
+        return ByteRange::Full;
+    };
+    let end = if end.is_empty() {
+        None
+    } else {
+        match end.parse::<usize>() {
+            Ok(end) if end >= start => Some(end),
+            _ => return ByteRange::Full,
+        }
+    };
+
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(start, end.map_or(len - 1, |e| e.min(len - 1)))
+}
+
+/// Narrows a `200` response to the requested range: `206 Partial Content`
+/// with `Content-Range`, or `416` when the range can't be served.
+pub fn apply(range_header: Option<&str>, response: &mut Response) {
+    if response.status_code != 200 {
+        return;
+    }
+    response
+        .headers
+        .insert("Accept-Ranges".to_string(), "bytes".to_string());
+    let Some(header) = range_header else {
+        return;
+    };
+
+    let len = response.body.len();
+    match parse(header, len) {
+        ByteRange::Full => {}
+        ByteRange::Partial(start, end) => {
+            response.status_code = 206;
+            response.body = response.body[start..=end].to_vec();
+            response.headers.insert(
+                "Content-Range".to_string(),
+                format!("bytes {}-{}/{}", start, end, len),
+            );
+        }
+        ByteRange::Unsatisfiable => {
+            response.status_code = 416;
+            response.body = Vec::new();
+            response
+                .headers
+                .insert("Content-Range".to_string(), format!("bytes */{}", len));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn body(len: usize) -> Response {
+        Response {
+            status_code: 200,
+            headers: HashMap::new(),
+            body: (0..len).map(|i| i as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn serves_a_bounded_range() {
+        let mut response = body(1000);
+
+        apply(Some("bytes=0-99"), &mut response);
+
+        assert_eq!(response.status_code, 206);
+        assert_eq!(response.body.len(), 100);
+        assert_eq!(response.body[99], 99);
+        assert_eq!(response.headers["Content-Range"], "bytes 0-99/1000");
+    }
+
+    #[test]
+    fn serves_open_ended_and_suffix_ranges() {
+        assert_eq!(parse("bytes=100-", 1000), ByteRange::Partial(100, 999));
+        assert_eq!(parse("bytes=-100", 1000), ByteRange::Partial(900, 999));
+        assert_eq!(parse("bytes=-5000", 1000), ByteRange::Partial(0, 999));
+
+        let mut response = body(1000);
+        apply(Some("bytes=-100"), &mut response);
+        assert_eq!(response.status_code, 206);
+        assert_eq!(response.body.len(), 100);
+        assert_eq!(response.headers["Content-Range"], "bytes 900-999/1000");
+    }
+
+    #[test]
+    fn answers_416_for_unsatisfiable_ranges() {
+        let mut response = body(1000);
+
+        apply(Some("bytes=1000-1100"), &mut response);
+
+        assert_eq!(response.status_code, 416);
+        assert!(response.body.is_empty());
+        assert_eq!(response.headers["Content-Range"], "bytes */1000");
+        assert_eq!(parse("bytes=-0", 1000), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignores_malformed_ranges() {
+        let mut response = body(10);
+
+        apply(Some("bytes=5-2"), &mut response);
+
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body.len(), 10);
+        assert_eq!(parse("items=0-5", 10), ByteRange::Full);
+        assert_eq!(parse("bytes=0-1,4-5", 10), ByteRange::Full);
+    }
+}
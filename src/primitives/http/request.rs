@@ -1,19 +1,332 @@
 use std::collections::HashMap;
 use std::fmt;
-use tokio::net::TcpStream;
+use tokio::io::BufReader;
 
+use super::media_type::MediaType;
+use super::method::Method;
+use super::multipart::{self, Part};
+use super::stream::Stream;
+use super::trace_context::TraceContext;
+use crate::config;
+use crate::util::url::{normalize_path, parse_urlencoded};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
 use chrono::{DateTime, Utc};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 pub struct Request {
-    pub method: String,
+    pub method: Method,
     pub url: String,
+    /// Protocol from the request line, e.g. `HTTP/1.1`.
+    pub version: String,
     pub headers: HashMap<String, String>,
-    pub body: String,
-    pub stream: TcpStream,
+    pub body: Vec<u8>,
+    /// The connection, buffered; it may already hold the start of the next
+    /// pipelined request, so keep-alive reuses this reader
+    pub stream: BufReader<Stream>,
     pub remote_addr: Option<SocketAddr>,
     pub timestamp: DateTime<Utc>,
+    /// First value of each query key; see `query_all` for repeated keys.
     pub query_params: HashMap<String, String>,
+    /// Every decoded query pair, in the order they appear in the URL.
+    pub query_pairs: Vec<(String, String)>,
+    /// `:name` segments captured by the matched route; filled by the router.
+    pub path_params: HashMap<String, String>,
+    /// Correlation id: the incoming request-id header, or a fresh UUID.
+    pub request_id: String,
+    /// Trace this request belongs to, continued from `traceparent` if sent.
+    pub trace: TraceContext,
+    /// Set by handlers that wrote their response straight to `stream` (e.g.
+    /// `sse::stream`); the server then only closes the connection.
+    pub response_sent: bool,
+}
+
+/// A request target split the way the server stores it on `Request`.
+pub struct Target {
+    /// Normalized, so `/admin/../users` or `//users` can't slip past a
+    /// route pattern.
+    pub path: String,
+    /// `path` plus the original query string.
+    pub url: String,
+    pub query_params: HashMap<String, String>,
+    pub query_pairs: Vec<(String, String)>,
+}
+
+impl Target {
+    pub fn parse(target: &str) -> Target {
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+        // Repeated keys keep their first value in query_params and all of
+        // them in query_pairs
+        let query_pairs = query.map(parse_urlencoded).unwrap_or_default();
+        let mut query_params = HashMap::new();
+        for (key, value) in &query_pairs {
+            query_params
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+
+        let path = normalize_path(path);
+        let url = match query {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.clone(),
+        };
+        Target {
+            path,
+            url,
+            query_params,
+            query_pairs,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Request {
+    pub fn method_str(&self) -> &str {
+        self.method.as_str()
+    }
+
+    /// The request target without its query string (`/users?page=2` gives
+    /// `/users`), already normalized by the server.
+    pub fn path(&self) -> &str {
+        self.url.split_once('?').map_or(&self.url, |(path, _)| path)
+    }
+
+    /// First value of a query parameter (`?tag=a&tag=b` gives `a`).
+    pub fn query_param(&self, key: &str) -> Option<&str> {
+        self.query_params.get(key).map(|v| v.as_str())
+    }
+
+    /// Every value of a repeated query parameter, in URL order.
+    pub fn query_all(&self, key: &str) -> Vec<&str> {
+        self.query_pairs
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// Value captured by a `:name` segment of the matched route pattern.
+    pub fn path_param(&self, name: &str) -> Option<&str> {
+        self.path_params.get(name).map(|v| v.as_str())
+    }
+
+    /// Case-insensitive header lookup; `Content-Type`, `content-type` and
+    /// `CONTENT-TYPE` all resolve to the same header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|v| v.as_str())
+    }
+
+    /// The parsed `Content-Type` header, split into type/subtype, charset and
+    /// the remaining parameters.
+    pub fn content_type(&self) -> Option<MediaType> {
+        self.header("Content-Type").and_then(MediaType::parse)
+    }
+
+    /// The body as UTF-8 text; binary bodies should use `body` directly.
+    pub fn body_string(&self) -> Result<String, String> {
+        String::from_utf8(self.body.clone())
+            .map_err(|e| format!("Request body is not valid UTF-8: {}", e))
+    }
+
+    /// Deserializes a JSON body, requiring an `application/json` content type.
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, String> {
+        if !self
+            .content_type()
+            .is_some_and(|ct| ct.is("application/json"))
+        {
+            return Err(format!(
+                "Expected Content-Type 'application/json', got '{}'",
+                self.header("Content-Type").unwrap_or("")
+            ));
+        }
+        if self.body.trim_ascii().is_empty() {
+            return Err("Request body is empty, expected a JSON document".to_string());
+        }
+        serde_json::from_slice(&self.body).map_err(|e| format!("Invalid JSON body: {}", e))
+    }
+
+    /// Decodes an `application/x-www-form-urlencoded` body. Repeated keys keep
+    /// the last value; use `form_multi` to get all of them.
+    pub fn form(&self) -> HashMap<String, String> {
+        self.form_pairs().into_iter().collect()
+    }
+
+    pub fn form_multi(&self) -> HashMap<String, Vec<String>> {
+        let mut form: HashMap<String, Vec<String>> = HashMap::new();
+        for (key, value) in self.form_pairs() {
+            form.entry(key).or_default().push(value);
+        }
+        form
+    }
+
+    fn form_pairs(&self) -> Vec<(String, String)> {
+        if !self
+            .content_type()
+            .is_some_and(|ct| ct.is("application/x-www-form-urlencoded"))
+        {
+            return Vec::new();
+        }
+        parse_urlencoded(&String::from_utf8_lossy(&self.body))
+    }
+
+    /// Splits a `multipart/form-data` body into its parts, using the boundary
+    /// from the `Content-Type` header.
+    pub fn multipart(&self) -> Result<Vec<Part>, String> {
+        let content_type = self
+            .content_type()
+            .filter(|ct| ct.is("multipart/form-data"))
+            .ok_or_else(|| {
+                format!(
+                    "Expected Content-Type 'multipart/form-data', got '{}'",
+                    self.header("Content-Type").unwrap_or("")
+                )
+            })?;
+        let boundary = content_type
+            .param("boundary")
+            .ok_or_else(|| "multipart/form-data Content-Type has no boundary".to_string())?;
+        multipart::parse(&self.body, boundary)
+    }
+
+    /// Token from an `Authorization: Bearer <token>` header. The scheme is
+    /// matched case-insensitively.
+    pub fn bearer_token(&self) -> Option<&str> {
+        let (scheme, token) = self.header("Authorization")?.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+        let token = token.trim();
+        if token.is_empty() { None } else { Some(token) }
+    }
+
+    /// Username and password from an `Authorization: Basic <base64>` header.
+    pub fn basic_auth(&self) -> Option<(String, String)> {
+        let (scheme, payload) = self.header("Authorization")?.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = BASE64_STANDARD.decode(payload.trim()).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        Some((username.to_string(), password.to_string()))
+    }
+
+    /// The originating client address. Forwarded headers (`X-Forwarded-For`,
+    /// then `X-Real-IP`) are only honored when `TRUST_PROXY=true`, since any
+    /// client can set them; otherwise this is the TCP peer address.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        if trust_proxy() {
+            let forwarded = forwarded_client(
+                self.header("X-Forwarded-For"),
+                &config::get().server.trusted_proxies,
+            );
+            if let Some(ip) = forwarded.or_else(|| self.header("X-Real-IP").and_then(parse_ip)) {
+                return Some(ip);
+            }
+        }
+        self.remote_addr.map(|addr| addr.ip())
+    }
+
+    /// Parses the `Cookie` header into name/value pairs. Values may contain `=`.
+    pub fn cookies(&self) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        if let Some(header) = self.header("Cookie") {
+            for pair in header.split(';') {
+                let pair = pair.trim();
+                if pair.is_empty() {
+                    continue;
+                }
+                if let Some((name, value)) = pair.split_once('=') {
+                    cookies.insert(name.trim().to_string(), value.trim().to_string());
+                }
+            }
+        }
+        cookies
+    }
+
+    /// Headers with credential values (Authorization, Cookie, API keys, plus
+    /// any in `LOG_REDACT_HEADERS`) half-masked, for logging.
+    fn masked_headers(&self) -> HashMap<String, String> {
+        let redacted = &config::get().server.log_redact_headers;
+        let mut obfuscated_headers = self.headers.clone();
+        for (key, value) in obfuscated_headers.iter_mut() {
+            if redacted.contains(&key.to_ascii_lowercase()) {
+                let len = value.len();
+                if len > 4 {
+                    let half = len / 2;
+                    let (first, _) = value.split_at(half);
+                    let masked = format!("{}{}", first, "*".repeat(len - half));
+                    *value = masked;
+                } else {
+                    *value = "****".to_string();
+                }
+            }
+        }
+        obfuscated_headers
+    }
+
+    /// One-line JSON access log entry for log aggregators (`LOG_FORMAT=json`).
+    pub fn log_json(&self, status_code: u16, duration_ms: f64) -> String {
+        serde_json::json!({
+            "timestamp": self.timestamp.to_rfc3339(),
+            "request_id": self.request_id,
+            "trace_id": self.trace.trace_id,
+            "method": self.method.as_str(),
+            "url": self.url,
+            "status": status_code,
+            "duration_ms": duration_ms,
+            "remote_addr": self.remote_addr.map(|a| a.to_string()),
+            "headers": self.masked_headers(),
+        })
+        .to_string()
+    }
+}
+
+fn trust_proxy() -> bool {
+    config::get().server.trust_proxy
+}
+
+/// The client as seen by our own proxies: each proxy appends the address it
+/// received from, so entries are read right to left, skipping
+/// `TRUSTED_PROXIES`. Anything left of the first untrusted hop was written
+/// by the client and may be forged.
+fn forwarded_client(forwarded_for: Option<&str>, trusted: &[IpAddr]) -> Option<IpAddr> {
+    forwarded_for?
+        .rsplit(',')
+        .map(parse_ip)
+        .find(|ip| !ip.is_some_and(|ip| trusted.contains(&ip)))
+        .flatten()
+}
+
+/// Accepts bare IPv4/IPv6 addresses as well as `ip:port` and `[ipv6]:port`.
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    value
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            value
+                .strip_prefix('[')
+                .and_then(|v| v.strip_suffix(']'))
+                .and_then(|v| v.parse::<IpAddr>().ok())
+        })
+}
+
+/// Header carrying the correlation id in both directions (`REQUEST_ID_HEADER`).
+pub fn request_id_header() -> String {
+    config::get().server.request_id_header.clone()
+}
+
+/// Whether an incoming correlation id can be reused: 1 to 128 visible ASCII
+/// characters, so it can't smuggle control bytes or line breaks into the
+/// logs or the echoed response header.
+pub fn is_valid_request_id(value: &str) -> bool {
+    (1..=128).contains(&value.len()) && value.bytes().all(|b| (0x21..=0x7e).contains(&b))
 }
 
 impl fmt::Display for Request {
@@ -33,27 +346,12 @@ impl fmt::Display for Request {
                 format!("{MAGENTA}unknown{RESET}", MAGENTA = MAGENTA, RESET = RESET)
             });
 
-        // Obfuscate Authorization-related headers
-        let mut obfuscated_headers = self.headers.clone();
-        for (key, value) in obfuscated_headers.iter_mut() {
-            let key_lower = key.to_ascii_lowercase();
-            if key_lower == "authorization" || key_lower == "proxy-authorization" {
-                let len = value.len();
-                if len > 4 {
-                    let half = len / 2;
-                    let (first, _) = value.split_at(half);
-                    let masked = format!("{}{}", first, "*".repeat(len - half));
-                    *value = masked;
-                } else {
-                    *value = "****".to_string();
-                }
-            }
-        }
+        let obfuscated_headers = self.masked_headers();
         write!(
             f,
-            "{CYAN}[{timestamp}]{RESET} {GREEN}INFO{RESET} {addr} \"{YELLOW}{method}{RESET} {BLUE}{url}{RESET}\"\nHeaders: {:#?}\nBody: {}",
+            "{CYAN}[{timestamp}]{RESET} {GREEN}INFO{RESET} {addr} {request_id} \"{YELLOW}{method}{RESET} {BLUE}{url}{RESET}\"\nHeaders: {:#?}\nBody: {}",
             obfuscated_headers,
-            self.body,
+            String::from_utf8_lossy(&self.body),
             CYAN = CYAN,
             GREEN = GREEN,
             YELLOW = YELLOW,
@@ -61,8 +359,126 @@ impl fmt::Display for Request {
             RESET = RESET,
             timestamp = self.timestamp.to_rfc3339(),
             addr = addr,
+            request_id = self.request_id,
             method = self.method,
             url = self.url
         )
     }
 }
+
+#[cfg(all(test, unix))]
+impl Request {
+    /// A bodiless request over a throwaway socket, for unit tests; `url` is
+    /// split and normalized as the server does. Must be called inside a
+    /// Tokio runtime.
+    pub fn for_test(method: Method, url: &str, headers: &[(&str, &str)]) -> Request {
+        let (stream, _peer) = tokio::net::UnixStream::pair().expect("socket pair");
+        let target = Target::parse(url);
+        Request {
+            method,
+            url: target.url,
+            version: "HTTP/1.1".to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_ascii_lowercase(), value.to_string()))
+                .collect(),
+            body: Vec::new(),
+            stream: BufReader::new(Stream::Unix(stream)),
+            remote_addr: None,
+            timestamp: Utc::now(),
+            query_params: target.query_params,
+            query_pairs: target.query_pairs,
+            path_params: HashMap::new(),
+            request_id: "test".to_string(),
+            trace: TraceContext::from_traceparent(None),
+            response_sent: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::http::head;
+
+    #[test]
+    fn request_ids_must_be_visible_ascii() {
+        assert!(is_valid_request_id("abc-123_DEF"));
+        assert!(is_valid_request_id(&"a".repeat(128)));
+
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id(&"a".repeat(129)));
+        assert!(!is_valid_request_id("two words"));
+        assert!(!is_valid_request_id("id\r\nSet-Cookie: x=1"));
+        assert!(!is_valid_request_id("tab\tid"));
+        assert!(!is_valid_request_id("caf\u{e9}"));
+    }
+
+    #[tokio::test]
+    async fn header_lookup_ignores_case() {
+        let lines = ["POST / HTTP/1.1", "CoNtEnT-LeNgTh: 42"].map(String::from);
+        let mut request = Request::for_test(Method::Post, "/", &[]);
+        request.headers = head::parse(&lines).unwrap().headers;
+
+        assert_eq!(request.header("Content-Length"), Some("42"));
+        assert_eq!(request.header("content-length"), Some("42"));
+        assert_eq!(request.header("CONTENT-LENGTH"), Some("42"));
+        assert_eq!(request.header("Content-Type"), None);
+    }
+
+    #[tokio::test]
+    async fn repeated_query_keys_keep_every_value() {
+        let request = Request::for_test(Method::Get, "/issues?tag=a&tag=b&tag=c&status=open", &[]);
+
+        assert_eq!(request.query_all("tag"), ["a", "b", "c"]);
+        assert_eq!(request.query_param("tag"), Some("a"));
+        assert_eq!(request.query_all("status"), ["open"]);
+        assert_eq!(request.query_param("status"), Some("open"));
+        assert!(request.query_all("missing").is_empty());
+    }
+
+    #[tokio::test]
+    async fn path_drops_the_query_string() {
+        let with_query = Request::for_test(Method::Get, "/users/42?x=1", &[]);
+        let without_query = Request::for_test(Method::Get, "/users/42", &[]);
+
+        assert_eq!(with_query.path(), "/users/42");
+        assert_eq!(without_query.path(), "/users/42");
+        assert_eq!(with_query.url, "/users/42?x=1");
+    }
+
+    #[tokio::test]
+    async fn path_is_normalized_before_routing() {
+        let request = Request::for_test(Method::Get, "/users/../admin?x=1", &[]);
+
+        assert_eq!(request.path(), "/admin");
+        assert_eq!(request.query_param("x"), Some("1"));
+    }
+
+    #[test]
+    fn forwarded_client_is_the_rightmost_untrusted_hop() {
+        let ip = |value: &str| value.parse::<IpAddr>().ok();
+        let proxies = [ip("10.0.0.2").unwrap(), ip("10.0.0.3").unwrap()];
+
+        // A forged leftmost entry is ignored
+        assert_eq!(
+            forwarded_client(Some("6.6.6.6, 203.0.113.7"), &[]),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            forwarded_client(Some("6.6.6.6, 203.0.113.7, 10.0.0.2, 10.0.0.3"), &proxies),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            forwarded_client(Some("[2001:db8::1]:4711"), &[]),
+            ip("2001:db8::1")
+        );
+    }
+
+    #[test]
+    fn forwarded_client_gives_up_on_garbage() {
+        assert_eq!(forwarded_client(None, &[]), None);
+        assert_eq!(forwarded_client(Some("1.2.3.4, not-an-ip"), &[]), None);
+        assert_eq!(forwarded_client(Some(""), &[]), None);
+    }
+}
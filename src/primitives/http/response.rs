@@ -1,34 +1,74 @@
+use super::compression::{self, Encoding};
+use super::status::StatusCode;
 use std::collections::HashMap;
 
+#[derive(Clone)]
 pub struct Response {
+    /// Kept as a plain `u16` rather than a `StatusCode`: every handler,
+    /// middleware and scaffolded controller builds responses as
+    /// `Response { status_code: 200, .. }`, and a typed field would break all
+    /// of them for no runtime gain. Use `status()` for the typed view, or
+    /// `StatusCode::NOT_FOUND.as_u16()` when building one.
     pub status_code: u16,
     pub headers: HashMap<String, String>,
-    pub body: String,
+    /// Raw body bytes; text bodies are built with `"...".into()` or `string.into()`.
+    pub body: Vec<u8>,
 }
 
-impl Response {
-    fn status_text(code: u16) -> &'static str {
-        match code {
-            200 => "OK",
-            201 => "Created",
-            204 => "No Content",
-            400 => "Bad Request",
-            401 => "Unauthorized",
-            403 => "Forbidden",
-            404 => "Not Found",
-            500 => "Internal Server Error",
-            501 => "Not Implemented",
-            502 => "Bad Gateway",
-            503 => "Service Unavailable",
-            _ => "Unknown",
+/// Adds `field` to the `Vary` header, keeping whatever it already lists.
+pub fn add_vary(headers: &mut HashMap<String, String>, field: &str) {
+    match headers.get_mut("Vary") {
+        Some(vary)
+            if vary
+                .split(',')
+                .any(|f| f.trim() == "*" || f.trim().eq_ignore_ascii_case(field)) => {}
+        Some(vary) => {
+            vary.push_str(", ");
+            vary.push_str(field);
+        }
+        None => {
+            headers.insert("Vary".to_string(), field.to_string());
         }
     }
+}
+
+impl Response {
+    /// The typed status, or `None` if `status_code` isn't a valid
+    /// three-digit code.
+    pub fn status(&self) -> Option<StatusCode> {
+        StatusCode::from_u16(self.status_code)
+    }
 
     pub fn to_bytes(&self) -> Vec<u8> {
+        self.serialize(&self.body, None)
+    }
+
+    /// Like `to_bytes`, but compresses the body when the client's
+    /// `Accept-Encoding` allows it and the body is large enough. Responses
+    /// that already set `Content-Encoding`, `Content-Length` or
+    /// `Content-Range` are left alone.
+    pub fn to_bytes_for(&self, accept_encoding: Option<&str>) -> Vec<u8> {
+        if self.headers.contains_key("Content-Encoding")
+            || self.headers.contains_key("Content-Length")
+            || self.headers.contains_key("Content-Range")
+        {
+            return self.to_bytes();
+        }
+        let content_type = self.headers.get("Content-Type").map(|v| v.as_str());
+        match compression::choose(accept_encoding, content_type, self.body.len()) {
+            Some(encoding) => match compression::compress(&self.body, encoding) {
+                Ok(compressed) => self.serialize(&compressed, Some(encoding)),
+                Err(_) => self.to_bytes(),
+            },
+            None => self.to_bytes(),
+        }
+    }
+
+    fn serialize(&self, body: &[u8], encoding: Option<Encoding>) -> Vec<u8> {
         let status_line = format!(
             "HTTP/1.1 {} {}\r\n",
             self.status_code,
-            Self::status_text(self.status_code)
+            self.status().map_or("Unknown", StatusCode::reason_phrase)
         );
         let mut response = status_line;
 
@@ -39,15 +79,114 @@ impl Response {
             response.push_str(&format!("{}: {}\r\n", key, value));
         }
 
+        if let Some(encoding) = encoding {
+            response.push_str(&format!("Content-Encoding: {}\r\n", encoding.as_str()));
+            response.push_str("Vary: Accept-Encoding\r\n");
+        }
+
         if !has_content_length {
-            response.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+            response.push_str(&format!("Content-Length: {}\r\n", body.len()));
         }
 
         if !has_connection {
             response.push_str("Connection: close\r\n");
         }
         response.push_str("\r\n");
-        response.push_str(&self.body);
-        response.into_bytes()
+
+        let mut bytes = response.into_bytes();
+        bytes.extend_from_slice(body);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status_code: u16) -> Response {
+        Response {
+            status_code,
+            headers: HashMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn status_line_carries_the_reason_phrase() {
+        let line = |code| {
+            let bytes = response(code).to_bytes();
+            let text = String::from_utf8(bytes).unwrap();
+            text.lines().next().unwrap().to_string()
+        };
+
+        assert_eq!(line(200), "HTTP/1.1 200 OK");
+        assert_eq!(line(404), "HTTP/1.1 404 Not Found");
+        assert_eq!(line(418), "HTTP/1.1 418 Client Error");
+        assert_eq!(line(42), "HTTP/1.1 42 Unknown");
+    }
+
+    fn json(body: String) -> Response {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        Response {
+            status_code: 200,
+            headers,
+            body: body.into_bytes(),
+        }
+    }
+
+    /// Header block and body of serialized response bytes.
+    fn split(bytes: &[u8]) -> (String, &[u8]) {
+        let end = bytes.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        (
+            String::from_utf8_lossy(&bytes[..end]).into_owned(),
+            &bytes[end + 4..],
+        )
+    }
+
+    #[test]
+    fn large_json_is_gzipped() {
+        let items: Vec<String> = (0..500).map(|i| format!("{{\"id\":{}}}", i)).collect();
+        let original = format!("[{}]", items.join(","));
+
+        let bytes = json(original.clone()).to_bytes_for(Some("gzip, deflate"));
+
+        let (head, body) = split(&bytes);
+        assert!(head.contains("Content-Encoding: gzip\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert!(body.len() < original.len());
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(body), &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, original);
+    }
+
+    #[test]
+    fn small_bodies_are_sent_uncompressed() {
+        let bytes = json("{\"id\":1}".to_string()).to_bytes_for(Some("gzip"));
+
+        let (head, body) = split(&bytes);
+        assert!(!head.contains("Content-Encoding"));
+        assert_eq!(body, b"{\"id\":1}");
+    }
+
+    #[test]
+    fn nothing_is_compressed_without_accept_encoding() {
+        let bytes = json("x".repeat(10_000)).to_bytes_for(None);
+
+        let (head, body) = split(&bytes);
+        assert!(!head.contains("Content-Encoding"));
+        assert_eq!(body.len(), 10_000);
+    }
+
+    #[test]
+    fn vary_fields_are_appended_once() {
+        let mut headers = HashMap::new();
+
+        add_vary(&mut headers, "Accept");
+        add_vary(&mut headers, "Origin");
+        add_vary(&mut headers, "origin");
+
+        assert_eq!(headers["Vary"], "Accept, Origin");
     }
 }
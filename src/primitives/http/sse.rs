@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, interval};
+
+use super::request::Request;
+use super::response::Response;
+use crate::routing::RouteParams;
+
+/// One Server-Sent Event. `data` may span several lines; `event` and `id`
+/// are optional.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    pub event: Option<String>,
+    pub id: Option<String>,
+    pub data: String,
+}
+
+#[allow(dead_code)]
+impl Event {
+    pub fn new(data: impl Into<String>) -> Self {
+        Event {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn event(mut self, name: impl Into<String>) -> Self {
+        self.event = Some(name.into());
+        self
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Wire format: `event:`/`id:` fields, one `data:` line per line of data,
+    /// then a blank line.
+    fn encode(&self) -> String {
+        let single_line = |v: &str| v.replace(['\r', '\n'], "");
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            out.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(id) = &self.id {
+            out.push_str(&format!("id: {}\n", single_line(id)));
+        }
+        for line in self.data.split('\n') {
+            out.push_str(&format!("data: {}\n", line.trim_end_matches('\r')));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// Streams `events` to the client as `text/event-stream`, writing and
+/// flushing each one as it arrives. Ends when the sender side is dropped or
+/// the client disconnects (the receiver is then dropped, so the producer's
+/// next `send` fails). The response is written directly to the connection,
+/// bypassing compression and buffering, and the connection is closed after.
+#[allow(dead_code)]
+pub async fn stream(request: &mut Request, mut events: mpsc::Receiver<Event>) -> Response {
+    request.response_sent = true;
+    let head = "HTTP/1.1 200 OK\r\n\
+        Content-Type: text/event-stream\r\n\
+        Cache-Control: no-cache\r\n\
+        X-Accel-Buffering: no\r\n\
+        Connection: close\r\n\r\n";
+    let stream = &mut request.stream;
+    if stream.write_all(head.as_bytes()).await.is_ok() && stream.flush().await.is_ok() {
+        let mut scratch = [0u8; 512];
+        loop {
+            tokio::select! {
+                event = events.recv() => {
+                    let Some(event) = event else { break };
+                    if stream.write_all(event.encode().as_bytes()).await.is_err()
+                        || stream.flush().await.is_err()
+                    {
+                        break;
+                    }
+                }
+                // Clients don't send anything on an event stream, so a read
+                // completing means EOF (disconnect) or an error
+                read = stream.read(&mut scratch) => {
+                    if matches!(read, Ok(0) | Err(_)) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "text/event-stream".to_string());
+    Response {
+        status_code: 200,
+        headers,
+        body: Vec::new(),
+    }
+}
+
+/// Example endpoint (`GET /events/counter`): emits an incrementing counter
+/// every second until the client goes away.
+pub async fn counter(request: &mut Request, _params: &RouteParams) -> Response {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::task::spawn_local(async move {
+        let mut ticker = interval(Duration::from_secs(1));
+        let mut count = 0u64;
+        loop {
+            ticker.tick().await;
+            count += 1;
+            let event = Event::new(count.to_string())
+                .event("counter")
+                .id(count.to_string());
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+    stream(request, rx).await
+}
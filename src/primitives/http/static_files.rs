@@ -0,0 +1,220 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use super::etag;
+use super::range;
+use super::request::Request;
+use super::response::Response;
+use super::streaming;
+use crate::config;
+use crate::routing::RouteParams;
+use crate::util::url::percent_decode;
+
+/// Files larger than this are streamed from disk instead of read into memory
+/// (unless a `Range` is requested).
+const STREAM_THRESHOLD: u64 = 1024 * 1024;
+
+/// Route handler for `GET /static/*path`, serving files from `STATIC_DIR`
+/// (default `public`).
+pub async fn serve(request: &mut Request, params: &RouteParams) -> Response {
+    let root = &config::get().server.static_dir;
+    serve_file(Path::new(root), params.get("path").unwrap_or(""), request).await
+}
+
+/// Resolves `request_path` under `root` and answers with the file. Paths that
+/// try to leave the root get 403, missing files 404, and a matching
+/// `If-None-Match`, or an `If-Modified-Since` at or after the file's mtime,
+/// gets 304. A `Range` header is honored (206/416). Files over 1 MiB are
+/// streamed with a weak size/mtime `ETag` instead of a content hash.
+pub async fn serve_file(root: &Path, request_path: &str, request: &mut Request) -> Response {
+    let Some(relative) = sanitize(request_path) else {
+        return plain(403, "Forbidden");
+    };
+
+    // Canonicalizing both sides also catches symlinks pointing outside root
+    let Ok(root) = tokio::fs::canonicalize(root).await else {
+        return plain(404, "Not Found");
+    };
+    let mut path = match tokio::fs::canonicalize(root.join(&relative)).await {
+        Ok(path) => path,
+        Err(_) => return plain(404, "Not Found"),
+    };
+    if !path.starts_with(&root) {
+        return plain(403, "Forbidden");
+    }
+    let Ok(mut metadata) = tokio::fs::metadata(&path).await else {
+        return plain(404, "Not Found");
+    };
+    if metadata.is_dir() {
+        path = path.join("index.html");
+        metadata = match tokio::fs::metadata(&path).await {
+            Ok(metadata) => metadata,
+            Err(_) => return plain(404, "Not Found"),
+        };
+    }
+    if !metadata.is_file() {
+        return plain(404, "Not Found");
+    }
+
+    let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+    let mut headers = HashMap::new();
+    if let Some(modified) = modified {
+        headers.insert("Last-Modified".to_string(), http_date(modified));
+        // If-None-Match takes precedence; it's checked against the ETag below
+        let not_modified = request.header("If-None-Match").is_none()
+            && request
+                .header("If-Modified-Since")
+                .and_then(|v| DateTime::parse_from_rfc2822(v.trim()).ok())
+                .is_some_and(|since| modified.timestamp() <= since.timestamp());
+        if not_modified {
+            return Response {
+                status_code: 304,
+                headers,
+                body: Vec::new(),
+            };
+        }
+    }
+
+    headers.insert("Content-Type".to_string(), content_type(&path).to_string());
+    headers.insert(
+        "Cache-Control".to_string(),
+        format!("public, max-age={}", max_age()),
+    );
+
+    if metadata.len() > STREAM_THRESHOLD && request.header("Range").is_none() {
+        let etag = format!(
+            "W/\"{:x}-{:x}\"",
+            metadata.len(),
+            modified.map_or(0, |m| m.timestamp())
+        );
+        let not_modified = request
+            .header("If-None-Match")
+            .is_some_and(|value| etag::matches(value, &etag));
+        headers.insert("ETag".to_string(), etag);
+        if not_modified {
+            return Response {
+                status_code: 304,
+                headers,
+                body: Vec::new(),
+            };
+        }
+        let Ok(file) = tokio::fs::File::open(&path).await else {
+            return plain(404, "Not Found");
+        };
+        let head = Response {
+            status_code: 200,
+            headers,
+            body: Vec::new(),
+        };
+        return streaming::send(request, head, file).await;
+    }
+
+    let body = match tokio::fs::read(&path).await {
+        Ok(body) => body,
+        Err(_) => return plain(404, "Not Found"),
+    };
+
+    let mut response = Response {
+        status_code: 200,
+        headers,
+        body,
+    };
+    etag::apply(request.header("If-None-Match"), &mut response);
+    range::apply(request.header("Range"), &mut response);
+    response
+}
+
+/// Decodes the request path into a relative path, or `None` if any segment
+/// is `..`, absolute, or otherwise not a plain file name.
+fn sanitize(request_path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for segment in request_path.split('/').filter(|s| !s.is_empty()) {
+        let segment = percent_decode(segment, false);
+        if segment.contains(['/', '\\', '\0']) {
+            return None;
+        }
+        let mut components = Path::new(&segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) => relative.push(name),
+            (Some(Component::CurDir), None) => {}
+            _ => return None,
+        }
+    }
+    Some(relative)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `Last-Modified` format, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn max_age() -> u64 {
+    config::get().server.static_max_age
+}
+
+fn plain(status_code: u16, body: &str) -> Response {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "text/plain".to_string());
+    Response {
+        status_code,
+        headers,
+        body: body.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_traversal() {
+        assert_eq!(sanitize("../secret.txt"), None);
+        assert_eq!(sanitize("css/../../etc/passwd"), None);
+        assert_eq!(sanitize("%2e%2e/etc/passwd"), None);
+        assert_eq!(sanitize("..%2fetc%2fpasswd"), None);
+        assert_eq!(sanitize("..%5c..%5cwindows"), None);
+    }
+
+    #[test]
+    fn keeps_plain_paths() {
+        assert_eq!(
+            sanitize("css/site.css"),
+            Some(PathBuf::from("css/site.css"))
+        );
+        assert_eq!(
+            sanitize("./img//logo.png"),
+            Some(PathBuf::from("img/logo.png"))
+        );
+        assert_eq!(sanitize(""), Some(PathBuf::new()));
+    }
+}
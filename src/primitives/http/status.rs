@@ -0,0 +1,215 @@
+use std::fmt;
+
+/// An HTTP status code. The constants cover the codes this server and its
+/// handlers use; any other three-digit code is accepted by `from_u16` and
+/// gets a generic reason phrase for its class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StatusCode(u16);
+
+#[allow(dead_code)]
+impl StatusCode {
+    pub const CONTINUE: StatusCode = StatusCode(100);
+    pub const SWITCHING_PROTOCOLS: StatusCode = StatusCode(101);
+    pub const OK: StatusCode = StatusCode(200);
+    pub const CREATED: StatusCode = StatusCode(201);
+    pub const ACCEPTED: StatusCode = StatusCode(202);
+    pub const NO_CONTENT: StatusCode = StatusCode(204);
+    pub const PARTIAL_CONTENT: StatusCode = StatusCode(206);
+    pub const MOVED_PERMANENTLY: StatusCode = StatusCode(301);
+    pub const FOUND: StatusCode = StatusCode(302);
+    pub const SEE_OTHER: StatusCode = StatusCode(303);
+    pub const NOT_MODIFIED: StatusCode = StatusCode(304);
+    pub const TEMPORARY_REDIRECT: StatusCode = StatusCode(307);
+    pub const PERMANENT_REDIRECT: StatusCode = StatusCode(308);
+    pub const BAD_REQUEST: StatusCode = StatusCode(400);
+    pub const UNAUTHORIZED: StatusCode = StatusCode(401);
+    pub const FORBIDDEN: StatusCode = StatusCode(403);
+    pub const NOT_FOUND: StatusCode = StatusCode(404);
+    pub const METHOD_NOT_ALLOWED: StatusCode = StatusCode(405);
+    pub const NOT_ACCEPTABLE: StatusCode = StatusCode(406);
+    pub const REQUEST_TIMEOUT: StatusCode = StatusCode(408);
+    pub const CONFLICT: StatusCode = StatusCode(409);
+    pub const GONE: StatusCode = StatusCode(410);
+    pub const LENGTH_REQUIRED: StatusCode = StatusCode(411);
+    pub const PRECONDITION_FAILED: StatusCode = StatusCode(412);
+    pub const PAYLOAD_TOO_LARGE: StatusCode = StatusCode(413);
+    pub const URI_TOO_LONG: StatusCode = StatusCode(414);
+    pub const UNSUPPORTED_MEDIA_TYPE: StatusCode = StatusCode(415);
+    pub const RANGE_NOT_SATISFIABLE: StatusCode = StatusCode(416);
+    pub const UNPROCESSABLE_ENTITY: StatusCode = StatusCode(422);
+    pub const TOO_MANY_REQUESTS: StatusCode = StatusCode(429);
+    pub const REQUEST_HEADER_FIELDS_TOO_LARGE: StatusCode = StatusCode(431);
+    pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode(500);
+    pub const NOT_IMPLEMENTED: StatusCode = StatusCode(501);
+    pub const BAD_GATEWAY: StatusCode = StatusCode(502);
+    pub const SERVICE_UNAVAILABLE: StatusCode = StatusCode(503);
+    pub const GATEWAY_TIMEOUT: StatusCode = StatusCode(504);
+    pub const HTTP_VERSION_NOT_SUPPORTED: StatusCode = StatusCode(505);
+
+    /// `None` unless `code` is a three-digit status (100-999).
+    pub fn from_u16(code: u16) -> Option<StatusCode> {
+        (100..=999).contains(&code).then_some(StatusCode(code))
+    }
+
+    pub fn as_u16(self) -> u16 {
+        self.0
+    }
+
+    pub fn reason_phrase(self) -> &'static str {
+        match self.0 {
+            100 => "Continue",
+            101 => "Switching Protocols",
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            204 => "No Content",
+            206 => "Partial Content",
+            301 => "Moved Permanently",
+            302 => "Found",
+            303 => "See Other",
+            304 => "Not Modified",
+            307 => "Temporary Redirect",
+            308 => "Permanent Redirect",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            406 => "Not Acceptable",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            410 => "Gone",
+            411 => "Length Required",
+            412 => "Precondition Failed",
+            413 => "Payload Too Large",
+            414 => "URI Too Long",
+            415 => "Unsupported Media Type",
+            416 => "Range Not Satisfiable",
+            422 => "Unprocessable Entity",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            505 => "HTTP Version Not Supported",
+            _ => match self.0 / 100 {
+                1 => "Informational",
+                2 => "Success",
+                3 => "Redirection",
+                4 => "Client Error",
+                _ => "Server Error",
+            },
+        }
+    }
+
+    pub fn is_success(self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    pub fn is_client_error(self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    pub fn is_server_error(self) -> bool {
+        self.0 >= 500
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.0
+    }
+}
+
+/// Formats as code and reason phrase, e.g. `404 Not Found`.
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.0, self.reason_phrase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_status_has_its_reason_phrase() {
+        let expected = [
+            (StatusCode::CONTINUE, "Continue"),
+            (StatusCode::SWITCHING_PROTOCOLS, "Switching Protocols"),
+            (StatusCode::OK, "OK"),
+            (StatusCode::CREATED, "Created"),
+            (StatusCode::ACCEPTED, "Accepted"),
+            (StatusCode::NO_CONTENT, "No Content"),
+            (StatusCode::PARTIAL_CONTENT, "Partial Content"),
+            (StatusCode::MOVED_PERMANENTLY, "Moved Permanently"),
+            (StatusCode::FOUND, "Found"),
+            (StatusCode::SEE_OTHER, "See Other"),
+            (StatusCode::NOT_MODIFIED, "Not Modified"),
+            (StatusCode::TEMPORARY_REDIRECT, "Temporary Redirect"),
+            (StatusCode::PERMANENT_REDIRECT, "Permanent Redirect"),
+            (StatusCode::BAD_REQUEST, "Bad Request"),
+            (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            (StatusCode::FORBIDDEN, "Forbidden"),
+            (StatusCode::NOT_FOUND, "Not Found"),
+            (StatusCode::METHOD_NOT_ALLOWED, "Method Not Allowed"),
+            (StatusCode::NOT_ACCEPTABLE, "Not Acceptable"),
+            (StatusCode::REQUEST_TIMEOUT, "Request Timeout"),
+            (StatusCode::CONFLICT, "Conflict"),
+            (StatusCode::GONE, "Gone"),
+            (StatusCode::LENGTH_REQUIRED, "Length Required"),
+            (StatusCode::PRECONDITION_FAILED, "Precondition Failed"),
+            (StatusCode::PAYLOAD_TOO_LARGE, "Payload Too Large"),
+            (StatusCode::URI_TOO_LONG, "URI Too Long"),
+            (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type"),
+            (StatusCode::RANGE_NOT_SATISFIABLE, "Range Not Satisfiable"),
+            (StatusCode::UNPROCESSABLE_ENTITY, "Unprocessable Entity"),
+            (StatusCode::TOO_MANY_REQUESTS, "Too Many Requests"),
+            (
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                "Request Header Fields Too Large",
+            ),
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
+            (StatusCode::NOT_IMPLEMENTED, "Not Implemented"),
+            (StatusCode::BAD_GATEWAY, "Bad Gateway"),
+            (StatusCode::SERVICE_UNAVAILABLE, "Service Unavailable"),
+            (StatusCode::GATEWAY_TIMEOUT, "Gateway Timeout"),
+            (
+                StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+                "HTTP Version Not Supported",
+            ),
+        ];
+        for (status, phrase) in expected {
+            assert_eq!(status.reason_phrase(), phrase, "{}", status.as_u16());
+            assert_eq!(
+                status.to_string(),
+                format!("{} {}", status.as_u16(), phrase)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_codes_fall_back_to_their_class() {
+        assert_eq!(
+            StatusCode::from_u16(299).unwrap().reason_phrase(),
+            "Success"
+        );
+        assert_eq!(
+            StatusCode::from_u16(418).unwrap().reason_phrase(),
+            "Client Error"
+        );
+        assert_eq!(
+            StatusCode::from_u16(599).unwrap().reason_phrase(),
+            "Server Error"
+        );
+    }
+
+    #[test]
+    fn from_u16_accepts_only_three_digit_codes() {
+        assert_eq!(StatusCode::from_u16(404), Some(StatusCode::NOT_FOUND));
+        assert_eq!(StatusCode::from_u16(99), None);
+        assert_eq!(StatusCode::from_u16(1000), None);
+    }
+}
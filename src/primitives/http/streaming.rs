@@ -0,0 +1,252 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::compression::{self, StreamEncoder};
+use super::method::Method;
+use super::request::{Request, request_id_header};
+use super::response::{Response, add_vary};
+use super::status::StatusCode;
+
+/// Bytes read from the source per chunk; also the most that is held in
+/// memory at once (plus the compressor's window).
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Sends `response`'s status and headers, then copies `body` to the client
+/// with `Transfer-Encoding: chunked`, one flushed chunk per read, so memory
+/// use stays bounded however large the body is. HTTP/1.0 clients don't
+/// understand chunked framing, so they get the raw bytes and the end of the
+/// body is marked by closing the connection. The body is compressed on the
+/// fly when `Accept-Encoding` allows it. `response.body` is ignored.
+///
+/// Like `sse::stream`, the response is written directly to the connection
+/// (which is closed afterwards); the returned head is for logging and
+/// metrics only.
+#[allow(dead_code)]
+pub async fn send<R: AsyncRead + Unpin>(
+    request: &mut Request,
+    mut response: Response,
+    mut body: R,
+) -> Response {
+    request.response_sent = true;
+    response.body = Vec::new();
+    response.headers.remove("Content-Length");
+    let chunked = request.version.eq_ignore_ascii_case("HTTP/1.1");
+    if chunked {
+        response
+            .headers
+            .insert("Transfer-Encoding".to_string(), "chunked".to_string());
+    }
+    response
+        .headers
+        .insert("Connection".to_string(), "close".to_string());
+    response
+        .headers
+        .insert(request_id_header(), request.request_id.clone());
+
+    let mut encoder = None;
+    if !response.headers.contains_key("Content-Encoding") {
+        let content_type = response.headers.get("Content-Type").map(|v| v.as_str());
+        if let Some(encoding) =
+            compression::choose_for_stream(request.header("Accept-Encoding"), content_type)
+        {
+            response.headers.insert(
+                "Content-Encoding".to_string(),
+                encoding.as_str().to_string(),
+            );
+            add_vary(&mut response.headers, "Accept-Encoding");
+            encoder = Some(StreamEncoder::new(encoding));
+        }
+    }
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        response.status_code,
+        response
+            .status()
+            .map_or("Unknown", StatusCode::reason_phrase)
+    );
+    for (key, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    head.push_str("\r\n");
+
+    let stream = &mut request.stream;
+    if stream.write_all(head.as_bytes()).await.is_err() || request.method == Method::Head {
+        return response;
+    }
+
+    let _ = copy_body(&mut body, stream, encoder, chunked).await;
+    response
+}
+
+/// Copies `body` to `out` one `CHUNK_SIZE` read at a time, compressing with
+/// `encoder` if given, as chunks when `chunked` and as raw bytes otherwise.
+///
+/// On an error the terminating chunk is never sent, so the client sees a
+/// truncated body rather than a complete-looking short one.
+async fn copy_body<R, W>(
+    body: &mut R,
+    out: &mut W,
+    mut encoder: Option<StreamEncoder>,
+    chunked: bool,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let read = body.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        match encoder.as_mut() {
+            Some(encoder) => write_part(out, &encoder.encode(&buf[..read])?, chunked).await?,
+            None => write_part(out, &buf[..read], chunked).await?,
+        }
+    }
+
+    if let Some(encoder) = encoder {
+        write_part(out, &encoder.finish()?, chunked).await?;
+    }
+    if chunked {
+        out.write_all(b"0\r\n\r\n").await?;
+    }
+    out.flush().await
+}
+
+/// Writes `data` as one `size CRLF data CRLF` chunk, or as-is when not
+/// `chunked`; empty data is skipped, since a zero-size chunk would end the
+/// body.
+async fn write_part<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    data: &[u8],
+    chunked: bool,
+) -> std::io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    if chunked {
+        stream
+            .write_all(format!("{:x}\r\n", data.len()).as_bytes())
+            .await?;
+    }
+    stream.write_all(data).await?;
+    if chunked {
+        stream.write_all(b"\r\n").await?;
+    }
+    stream.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::ReadBuf;
+
+    /// Records the largest single read handed out by `inner`.
+    struct CountingReader<R> {
+        inner: R,
+        largest: usize,
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let before = buf.filled().len();
+            let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+            self.largest = self.largest.max(buf.filled().len() - before);
+            poll
+        }
+    }
+
+    /// Discards what it is given, keeping only the total and largest write.
+    #[derive(Default)]
+    struct CountingWriter {
+        total: usize,
+        largest: usize,
+    }
+
+    impl AsyncWrite for CountingWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.total += buf.len();
+            self.largest = self.largest.max(buf.len());
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_copied_in_bounded_pieces() {
+        const SIZE: u64 = 10 * 1024 * 1024;
+        let mut body = CountingReader {
+            inner: tokio::io::repeat(b'x').take(SIZE),
+            largest: 0,
+        };
+        let mut out = CountingWriter::default();
+
+        copy_body(&mut body, &mut out, None, true).await.unwrap();
+
+        assert!(body.largest <= CHUNK_SIZE);
+        assert!(out.largest <= CHUNK_SIZE);
+        let chunks = SIZE as usize / CHUNK_SIZE;
+        let framing = chunks * "10000\r\n\r\n".len() + "0\r\n\r\n".len();
+        assert_eq!(out.total, SIZE as usize + framing);
+    }
+
+    #[tokio::test]
+    async fn chunked_output_is_framed() {
+        let mut out = Vec::new();
+
+        copy_body(&mut &b"hello world"[..], &mut out, None, true)
+            .await
+            .unwrap();
+
+        assert_eq!(out, b"b\r\nhello world\r\n0\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn unchunked_output_is_the_raw_body() {
+        let mut out = Vec::new();
+
+        copy_body(&mut &b"hello world"[..], &mut out, None, false)
+            .await
+            .unwrap();
+
+        assert_eq!(out, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn compressed_output_decodes_to_the_body() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let body = "hello world ".repeat(10_000);
+        let mut out = Vec::new();
+        let encoder = StreamEncoder::new(compression::Encoding::Gzip);
+
+        copy_body(&mut body.as_bytes(), &mut out, Some(encoder), false)
+            .await
+            .unwrap();
+
+        let mut decoded = String::new();
+        GzDecoder::new(&out[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+    }
+}
@@ -0,0 +1,112 @@
+use uuid::Uuid;
+
+/// W3C Trace Context for one request: the trace it belongs to and the span
+/// this server opened for it. Continues an incoming `traceparent` header
+/// (`00-<trace-id>-<parent-id>-<flags>`) or starts a new trace. The ids only
+/// tag spans and logs; nothing is exported to a collector.
+#[derive(Debug, Clone)]
+pub struct TraceContext {
+    /// 32 lowercase hex characters.
+    pub trace_id: String,
+    /// 16 lowercase hex characters identifying this server's span.
+    pub span_id: String,
+    /// The caller's span id, when the request carried a valid `traceparent`.
+    pub parent_id: Option<String>,
+    pub flags: u8,
+}
+
+#[allow(dead_code)]
+impl TraceContext {
+    /// Joins the caller's trace when `traceparent` is valid; malformed
+    /// headers are ignored and a fresh trace is started instead.
+    pub fn from_traceparent(traceparent: Option<&str>) -> Self {
+        match traceparent.and_then(parse) {
+            Some((trace_id, parent_id, flags)) => TraceContext {
+                trace_id,
+                span_id: new_span_id(),
+                parent_id: Some(parent_id),
+                flags,
+            },
+            None => TraceContext {
+                trace_id: Uuid::new_v4().simple().to_string(),
+                span_id: new_span_id(),
+                parent_id: None,
+                flags: 0x01,
+            },
+        }
+    }
+
+    /// `traceparent` value with this request's span as the parent. The server
+    /// makes no outgoing calls itself, so a handler that calls another
+    /// service has to add this header to that request.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+}
+
+fn new_span_id() -> String {
+    Uuid::new_v4().simple().to_string()[..16].to_string()
+}
+
+fn parse(value: &str) -> Option<(String, String, u8)> {
+    let mut fields = value.trim().split('-');
+    let version = fields.next()?;
+    let trace_id = fields.next()?;
+    let parent_id = fields.next()?;
+    let flags = fields.next()?;
+    // Version 00 has exactly four fields; later versions may append more
+    if version.len() != 2 || !is_hex(version) || version == "ff" {
+        return None;
+    }
+    if version == "00" && fields.next().is_some() {
+        return None;
+    }
+    let valid_id =
+        |id: &str, len: usize| id.len() == len && is_hex(id) && id.bytes().any(|b| b != b'0');
+    if !valid_id(trace_id, 32) || !valid_id(parent_id, 16) || flags.len() != 2 {
+        return None;
+    }
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    Some((trace_id.to_string(), parent_id.to_string(), flags))
+}
+
+/// Lowercase hex only, as the spec requires.
+fn is_hex(value: &str) -> bool {
+    value
+        .bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn continues_a_valid_traceparent() {
+        let trace = TraceContext::from_traceparent(Some(PARENT));
+
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(trace.parent_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_ne!(trace.span_id, "00f067aa0ba902b7");
+        assert_eq!(
+            trace.traceparent(),
+            format!("00-4bf92f3577b34da6a3ce929d0e0e4736-{}-01", trace.span_id)
+        );
+    }
+
+    #[test]
+    fn starts_a_new_trace_for_malformed_headers() {
+        for bad in [
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            let trace = TraceContext::from_traceparent(Some(bad));
+            assert_eq!(trace.parent_id, None, "{}", bad);
+            assert_eq!(trace.trace_id.len(), 32);
+        }
+    }
+}
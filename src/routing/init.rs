@@ -1,9 +1,23 @@
-use crate::routing::Route;
 use crate::domain::user::controller::UserController;
+use crate::primitives::http::method::Method;
+use crate::primitives::http::sse;
+use crate::primitives::http::static_files;
+use crate::route;
+use crate::routing::Route;
 
 pub fn init_routes() -> Vec<Route> {
     let mut routes = Vec::new();
 
-        routes.extend(UserController::routes());
-routes
+    routes.extend(UserController::routes());
+    routes.push(Route::new(
+        Method::Get,
+        &["static", "*path"],
+        vec![route!(static_files::serve)],
+    ));
+    routes.push(Route::new(
+        Method::Get,
+        &["events", "counter"],
+        vec![route!(sse::counter)],
+    ));
+    routes
 }
@@ -1,14 +1,16 @@
+use crate::primitives::http::method::Method;
+use crate::primitives::http::problem::Problem;
 use crate::primitives::http::request::Request;
 use crate::primitives::http::response::Response;
+use crate::primitives::http::status::StatusCode;
+use crate::util::future::BoxFuture;
 use std::collections::HashMap;
-use std::future::Future;
-use std::pin::Pin;
 use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
 pub mod init;
 pub use init::init_routes;
 
-pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
 pub type ControllerHandler =
     Box<dyn for<'a> Fn(&'a mut Request, &'a RouteParams) -> BoxFuture<'a, Response> + Send + Sync>;
 pub type MiddlewareHandler = Box<
@@ -41,23 +43,93 @@ impl RouteParams {
 }
 
 pub struct Route {
-    pub method: &'static str,
-    pub path: &'static [&'static str],
+    pub method: Method,
+    pub path: Vec<&'static str>,
     pub handlers: Vec<Handler>,
+    /// Overrides `MAX_BODY_SIZE` for this route.
+    pub max_body_size: Option<usize>,
+    /// Overrides `REQUEST_READ_TIMEOUT_SECS` for this route.
+    pub read_timeout: Option<Duration>,
 }
 
+#[allow(dead_code)]
 impl Route {
-    pub fn new(
-        method: &'static str,
-        path: &'static [&'static str],
-        handlers: Vec<Handler>,
-    ) -> Self {
+    pub fn new(method: Method, path: &'static [&'static str], handlers: Vec<Handler>) -> Self {
+        Self {
+            method,
+            path: path.to_vec(),
+            handlers,
+            max_body_size: None,
+            read_timeout: None,
+        }
+    }
+
+    /// Same as `new`, but takes the path as a string pattern such as
+    /// `"/users/:id/posts/:post_id"`.
+    pub fn from_pattern(method: Method, pattern: &'static str, handlers: Vec<Handler>) -> Self {
         Self {
             method,
-            path,
+            path: pattern.split('/').filter(|s| !s.is_empty()).collect(),
             handlers,
+            max_body_size: None,
+            read_timeout: None,
         }
     }
+
+    /// Accepts bodies up to `bytes` on this route instead of the global
+    /// `MAX_BODY_SIZE`, e.g. more for an upload endpoint, less for a login.
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.max_body_size = Some(bytes);
+        self
+    }
+
+    /// Gives clients `timeout` to send the whole request on this route
+    /// instead of `REQUEST_READ_TIMEOUT_SECS`. It counts from the first byte,
+    /// so it must cover the headers as well as the body.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// The path as a pattern string, e.g. `/users/:id`.
+    pub fn pattern(&self) -> String {
+        format!("/{}", self.path.join("/"))
+    }
+}
+
+/// Collects routes registered with string patterns:
+///
+/// ```ignore
+/// let mut router = Router::new();
+/// router.route(Method::Get, "/users/:id", vec![route!(UserController::get_one)]);
+/// routes.extend(router.into_routes());
+/// ```
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+#[allow(dead_code)]
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route(
+        &mut self,
+        method: Method,
+        pattern: &'static str,
+        handlers: Vec<Handler>,
+    ) -> &mut Self {
+        self.routes
+            .push(Route::from_pattern(method, pattern, handlers));
+        self
+    }
+
+    pub fn into_routes(self) -> Vec<Route> {
+        self.routes
+    }
 }
 
 #[macro_export]
@@ -88,41 +160,67 @@ pub fn routes() -> &'static [Route] {
     ROUTES.get().map(|r| r.as_slice()).unwrap_or(&[])
 }
 
-pub async fn route(request: &mut Request) -> Response {
-    let path = request.url.split('?').next().unwrap_or("");
-
-    let segments: Vec<&str> = path
-        .trim_matches('/')
-        .split('/')
-        .filter(|s| !s.is_empty())
-        .collect();
+/// What a method and path resolve to among `routes`.
+enum Resolution<'r> {
+    Found(&'r Route, RouteParams),
+    /// The path matches, but only for these methods.
+    MethodNotAllowed(Vec<&'r str>),
+    NotFound,
+}
 
-    let routes = routes();
-    let mut path_matched = false;
+fn resolve<'r>(routes: &'r [Route], method: &Method, path: &str) -> Resolution<'r> {
+    let segments = path_segments(path);
+    let mut allowed: Vec<&str> = Vec::new();
 
     for route_def in routes {
-        let params = match path_match_params(route_def.path, &segments) {
+        let params = match path_match_params(&route_def.path, &segments) {
             Some(params) => params,
             None => continue,
         };
-        path_matched = true;
-        if route_def.method == request.method {
-            let mut handlers = route_def.handlers.clone();
-            handlers.reverse();
-            return next_handler(request, &params, &mut handlers).await;
+        if route_def.method == *method {
+            return Resolution::Found(route_def, params);
         }
+        if !allowed.contains(&route_def.method.as_str()) {
+            allowed.push(route_def.method.as_str());
+        }
+    }
+
+    if allowed.is_empty() {
+        Resolution::NotFound
+    } else {
+        Resolution::MethodNotAllowed(allowed)
     }
+}
 
-    if path_matched {
-        return method_not_allowed();
+/// The route a request for `method` and `path` will be dispatched to, so the
+/// server can apply its limits before reading the body.
+pub fn matching_route(method: &Method, path: &str) -> Option<&'static Route> {
+    match resolve(routes(), method, path) {
+        Resolution::Found(route, _) => Some(route),
+        _ => None,
     }
+}
+
+fn path_segments(path: &str) -> Vec<&str> {
+    path.trim_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect()
+}
 
-    let mut headers = HashMap::new();
-    headers.insert("Content-Type".to_string(), "text/plain".to_string());
-    Response {
-        status_code: 404,
-        headers,
-        body: "Not Found".to_string(),
+pub async fn route(request: &mut Request) -> Response {
+    match resolve(routes(), &request.method, request.path()) {
+        Resolution::Found(route_def, params) => {
+            tracing::Span::current().record("route", route_def.pattern().as_str());
+            request.path_params = params.params.clone();
+            let mut handlers = route_def.handlers.clone();
+            handlers.reverse();
+            next_handler(request, &params, &mut handlers).await
+        }
+        Resolution::MethodNotAllowed(allowed) => method_not_allowed(request, &allowed),
+        Resolution::NotFound => Problem::new(StatusCode::NOT_FOUND)
+            .detail(format!("No route matches {}", request.path()))
+            .to_response(request),
     }
 }
 
@@ -137,22 +235,30 @@ pub async fn next_handler(
             HandlerKind::Controller(controller) => controller(request, params).await,
         }
     } else {
-        let mut headers = HashMap::new();
-        headers.insert("Content-Type".to_string(), "text/plain".to_string());
-        Response {
-            status_code: 500,
-            headers,
-            body: "Middleware chain ended without controller".to_string(),
-        }
+        Problem::new(StatusCode::INTERNAL_SERVER_ERROR)
+            .detail("Middleware chain ended without controller")
+            .to_response(request)
     }
 }
 
 fn path_match_params(pattern: &[&str], segments: &[&str]) -> Option<RouteParams> {
+    let mut params = HashMap::new();
+
+    // A trailing `*name` segment captures the rest of the path (possibly empty)
+    let (pattern, segments) = match pattern.split_last() {
+        Some((last, head)) if last.starts_with('*') => {
+            if segments.len() < head.len() {
+                return None;
+            }
+            params.insert(last[1..].to_string(), segments[head.len()..].join("/"));
+            (head, &segments[..head.len()])
+        }
+        _ => (pattern, segments),
+    };
     if pattern.len() != segments.len() {
         return None;
     }
 
-    let mut params = HashMap::new();
     for (p, s) in pattern.iter().zip(segments.iter()) {
         if let Some(name) = p.strip_prefix(':') {
             params.insert(name.to_string(), (*s).to_string());
@@ -166,12 +272,101 @@ fn path_match_params(pattern: &[&str], segments: &[&str]) -> Option<RouteParams>
     Some(RouteParams { params })
 }
 
-fn method_not_allowed() -> Response {
-    let mut headers = HashMap::new();
-    headers.insert("Content-Type".to_string(), "text/plain".to_string());
-    Response {
-        status_code: 405,
-        headers,
-        body: "Method Not Allowed".to_string(),
+fn method_not_allowed(request: &Request, allowed: &[&str]) -> Response {
+    let mut response = Problem::new(StatusCode::METHOD_NOT_ALLOWED)
+        .detail(format!(
+            "{} is not supported here; use {}",
+            request.method,
+            allowed.join(", ")
+        ))
+        .to_response(request);
+    response
+        .headers
+        .insert("Allow".to_string(), allowed.join(", "));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route;
+
+    async fn ok(_request: &mut Request, _params: &RouteParams) -> Response {
+        Response {
+            status_code: 200,
+            headers: HashMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn user_routes() -> Vec<Route> {
+        vec![
+            Route::from_pattern(Method::Get, "/users/:id", vec![route!(ok)]),
+            Route::from_pattern(Method::Put, "/users/:id", vec![route!(ok)]),
+            Route::from_pattern(Method::Get, "/users", vec![route!(ok)]),
+        ]
+    }
+
+    #[test]
+    fn finds_the_route_for_method_and_path() {
+        let routes = user_routes();
+
+        let Resolution::Found(route, params) = resolve(&routes, &Method::Put, "/users/42") else {
+            panic!("expected a match");
+        };
+        assert_eq!(route.method, Method::Put);
+        assert_eq!(route.pattern(), "/users/:id");
+        assert_eq!(params.get("id"), Some("42"));
+    }
+
+    #[test]
+    fn method_mismatch_is_not_allowed() {
+        let routes = user_routes();
+
+        let Resolution::MethodNotAllowed(allowed) = resolve(&routes, &Method::Delete, "/users/42")
+        else {
+            panic!("expected 405");
+        };
+        assert_eq!(allowed, ["GET", "PUT"]);
+    }
+
+    #[test]
+    fn unknown_path_is_not_found() {
+        let routes = user_routes();
+
+        assert!(matches!(
+            resolve(&routes, &Method::Get, "/posts/42"),
+            Resolution::NotFound
+        ));
+        assert!(matches!(
+            resolve(&routes, &Method::Get, "/users/42/extra"),
+            Resolution::NotFound
+        ));
+    }
+
+    #[tokio::test]
+    async fn route_answers_405_with_allow_or_404() {
+        init(init_routes());
+
+        let mut request = Request::for_test(Method::Delete, "/user", &[]);
+        let response = route(&mut request).await;
+        assert_eq!(response.status_code, 405);
+        assert_eq!(response.headers["Allow"], "GET, POST");
+
+        let mut request = Request::for_test(Method::Get, "/no/such/page", &[]);
+        let response = route(&mut request).await;
+        assert_eq!(response.status_code, 404);
+        assert!(!response.headers.contains_key("Allow"));
+    }
+
+    #[test]
+    fn routes_carry_their_own_body_limits() {
+        init(init_routes());
+
+        let create_user = matching_route(&Method::Post, "/user").unwrap();
+        let static_file = matching_route(&Method::Get, "/static/app.js").unwrap();
+
+        assert_eq!(create_user.max_body_size, Some(16 * 1024));
+        assert_eq!(static_file.max_body_size, None);
     }
 }
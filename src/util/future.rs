@@ -0,0 +1,6 @@
+use std::future::Future;
+use std::pin::Pin;
+
+/// A pinned, boxed future that may borrow for `'a`; what route handlers and
+/// `db::with_transaction` closures return.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::config;
+use crate::db;
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+struct Histogram {
+    /// Per-bucket counts (not cumulative); the last slot is `+Inf`.
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+struct Registry {
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    latency: Mutex<Histogram>,
+    in_flight: AtomicI64,
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(|| Registry {
+        requests: Mutex::new(BTreeMap::new()),
+        latency: Mutex::new(Histogram {
+            buckets: [0; LATENCY_BUCKETS.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }),
+        in_flight: AtomicI64::new(0),
+    })
+}
+
+/// Path the metrics are served at (`METRICS_PATH`, default `/metrics`).
+pub fn path() -> &'static str {
+    &config::get().server.metrics_path
+}
+
+/// `METRICS_PORT`, when metrics should only be reachable on a separate admin
+/// listener instead of the public port.
+pub fn admin_port() -> Option<u16> {
+    config::get().server.metrics_port
+}
+
+/// Counts a request as in flight until the returned guard is dropped.
+pub fn track_in_flight() -> InFlight {
+    registry().in_flight.fetch_add(1, Ordering::Relaxed);
+    InFlight
+}
+
+pub struct InFlight;
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        registry().in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Methods counted under their own label; anything else is `OTHER`.
+const KNOWN_METHODS: [&str; 7] = ["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"];
+
+/// Records one finished request in the counter and latency histogram.
+///
+/// Extension methods share the `OTHER` label, so clients sending made-up
+/// methods can't grow the number of series without bound.
+pub fn record_request(method: &str, status_code: u16, elapsed: Duration) {
+    let method = if KNOWN_METHODS.contains(&method) {
+        method
+    } else {
+        "OTHER"
+    };
+    let registry = registry();
+    if let Ok(mut requests) = registry.requests.lock() {
+        *requests
+            .entry((method.to_string(), status_code))
+            .or_insert(0) += 1;
+    }
+    if let Ok(mut latency) = registry.latency.lock() {
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        latency.buckets[bucket] += 1;
+        latency.sum += secs;
+        latency.count += 1;
+    }
+}
+
+/// Every metric in the Prometheus text exposition format (version 0.0.4).
+pub fn render() -> String {
+    let registry = registry();
+    let mut out = String::new();
+
+    out.push_str("# HELP http_requests_total Requests served, by method and status code.\n");
+    out.push_str("# TYPE http_requests_total counter\n");
+    if let Ok(requests) = registry.requests.lock() {
+        for ((method, status), count) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{}\",status=\"{}\"}} {}",
+                escape_label(method),
+                status,
+                count
+            );
+        }
+    }
+
+    out.push_str("# HELP http_request_duration_seconds Time spent handling a request.\n");
+    out.push_str("# TYPE http_request_duration_seconds histogram\n");
+    if let Ok(latency) = registry.latency.lock() {
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(latency.buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            latency.count
+        );
+        let _ = writeln!(out, "http_request_duration_seconds_sum {}", latency.sum);
+        let _ = writeln!(out, "http_request_duration_seconds_count {}", latency.count);
+    }
+
+    out.push_str("# HELP http_requests_in_flight Requests currently being handled.\n");
+    out.push_str("# TYPE http_requests_in_flight gauge\n");
+    let _ = writeln!(
+        out,
+        "http_requests_in_flight {}",
+        registry.in_flight.load(Ordering::Relaxed)
+    );
+
+    if db::is_initialized() {
+        let status = db::pool_status();
+        out.push_str("# HELP db_pool_connections Open DB pool connections, by state.\n");
+        out.push_str("# TYPE db_pool_connections gauge\n");
+        let idle = status.idle as u64;
+        let active = (status.size as u64).saturating_sub(idle);
+        let _ = writeln!(out, "db_pool_connections{{state=\"idle\"}} {}", idle);
+        let _ = writeln!(out, "db_pool_connections{{state=\"active\"}} {}", active);
+    }
+
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The value of the sample line starting with `series`, or 0 if absent.
+    fn sample(scrape: &str, series: &str) -> u64 {
+        scrape
+            .lines()
+            .find_map(|line| line.strip_prefix(series))
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn scrape_shows_counter_increments() {
+        let series = "http_requests_total{method=\"GET\",status=\"299\"}";
+        let before = sample(&render(), series);
+
+        record_request("GET", 299, Duration::from_millis(3));
+        record_request("GET", 299, Duration::from_millis(7));
+
+        let scrape = render();
+        assert_eq!(sample(&scrape, series), before + 2);
+        assert!(scrape.contains("# TYPE http_requests_total counter"));
+        assert!(scrape.contains("http_request_duration_seconds_bucket{le=\"+Inf\"}"));
+    }
+
+    #[test]
+    fn extension_methods_share_one_label() {
+        record_request("PROPFIND", 298, Duration::from_millis(1));
+        record_request("X-MADE-UP", 298, Duration::from_millis(1));
+
+        let scrape = render();
+        assert_eq!(
+            sample(
+                &scrape,
+                "http_requests_total{method=\"OTHER\",status=\"298\"}"
+            ),
+            2
+        );
+        assert!(!scrape.contains("PROPFIND"));
+        assert!(!scrape.contains("X-MADE-UP"));
+    }
+}
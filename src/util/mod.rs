@@ -1 +1,5 @@
+pub mod future;
+pub mod metrics;
 pub mod pagination;
+pub mod telemetry;
+pub mod url;
@@ -0,0 +1,32 @@
+use tracing_subscriber::fmt::format::FmtSpan;
+
+use crate::config;
+
+/// `TRACING=true` routes logs through a `tracing` subscriber, so each line
+/// carries its request span (method, route, request_id, trace_id) and span
+/// close events report request and query latency.
+pub fn enabled() -> bool {
+    config::get().server.tracing
+}
+
+/// Installs the global subscriber when tracing is enabled. Spans are still
+/// created otherwise, but nothing records them.
+pub fn init() {
+    if !enabled() {
+        return;
+    }
+    let _ = tracing_subscriber::fmt()
+        .with_span_events(FmtSpan::CLOSE)
+        .with_target(false)
+        .try_init();
+}
+
+/// Prints a log line, through the subscriber when tracing is enabled so it
+/// is attached to the current span.
+pub fn emit(line: &str) {
+    if enabled() {
+        tracing::info!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
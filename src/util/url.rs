@@ -0,0 +1,144 @@
+/// Percent-decodes `input`, optionally treating `+` as a space (form and
+/// query-string encoding). Malformed `%` sequences are kept verbatim.
+pub fn percent_decode(input: &str, plus_as_space: bool) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                    (Some(hi), Some(lo)) => {
+                        out.push(hi << 4 | lo);
+                        i += 3;
+                    }
+                    _ => {
+                        out.push(b'%');
+                        i += 1;
+                    }
+                }
+            }
+            b'+' if plus_as_space => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Parses an `application/x-www-form-urlencoded` string into decoded
+/// key/value pairs, preserving order and repeated keys.
+pub fn parse_urlencoded(input: &str) -> Vec<(String, String)> {
+    input
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key, true), percent_decode(value, true))
+        })
+        .collect()
+}
+
+/// Normalizes an origin-form path: collapses repeated slashes and resolves
+/// `.`/`..` segments (including percent-encoded dots), never climbing above
+/// the root. A trailing slash is kept. Other forms (`*`, absolute URLs) are
+/// returned unchanged.
+pub fn normalize_path(path: &str) -> String {
+    if !path.starts_with('/') {
+        return path.to_string();
+    }
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment.to_ascii_lowercase().as_str() {
+            "" | "." | "%2e" => {}
+            ".." | ".%2e" | "%2e." | "%2e%2e" => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let mut normalized = format!("/{}", segments.join("/"));
+    if !segments.is_empty() && path.ends_with('/') {
+        normalized.push('/');
+    }
+    normalized
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_encoded_spaces() {
+        assert_eq!(percent_decode("John%20Doe", false), "John Doe");
+        assert_eq!(percent_decode("John+Doe", true), "John Doe");
+        assert_eq!(percent_decode("John+Doe", false), "John+Doe");
+    }
+
+    #[test]
+    fn keeps_malformed_sequences() {
+        assert_eq!(percent_decode("100%zz", true), "100%zz");
+        assert_eq!(percent_decode("50%", true), "50%");
+        assert_eq!(percent_decode("%2", true), "%2");
+    }
+
+    #[test]
+    fn decodes_multibyte_utf8() {
+        assert_eq!(percent_decode("caf%C3%A9", true), "café");
+        assert_eq!(percent_decode("%E2%82%AC5", true), "€5");
+    }
+
+    #[test]
+    fn parses_query_strings() {
+        assert_eq!(
+            parse_urlencoded("name=John+Doe&&city=S%C3%A3o%20Paulo&flag"),
+            vec![
+                ("name".to_string(), "John Doe".to_string()),
+                ("city".to_string(), "São Paulo".to_string()),
+                ("flag".to_string(), String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn collapses_repeated_slashes() {
+        assert_eq!(normalize_path("//users///42"), "/users/42");
+    }
+
+    #[test]
+    fn drops_dot_segments() {
+        assert_eq!(normalize_path("/users/./42/."), "/users/42");
+        assert_eq!(normalize_path("/users/%2E/42"), "/users/42");
+    }
+
+    #[test]
+    fn never_climbs_above_the_root() {
+        assert_eq!(normalize_path("/users/../admin"), "/admin");
+        assert_eq!(normalize_path("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(normalize_path("/%2e%2e/admin"), "/admin");
+        assert_eq!(normalize_path("/.."), "/");
+    }
+
+    #[test]
+    fn keeps_a_trailing_slash() {
+        assert_eq!(normalize_path("/users/"), "/users/");
+        assert_eq!(normalize_path("/users//"), "/users/");
+        assert_eq!(normalize_path("/"), "/");
+    }
+}
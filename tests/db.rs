@@ -0,0 +1,142 @@
+//! Round trips through the `db` helpers against a real Postgres. Ignored by
+//! default; run with `DATABASE_URL=... cargo test --test db -- --ignored`.
+
+use base_rust_web_api::db::{self, DbParam, SortOrder};
+use chrono::{TimeZone, Utc};
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::Row;
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
+
+/// Runs `test` on one runtime shared by every test, since the pool's
+/// connections are tied to the runtime that opened them.
+fn run<F: Future>(test: F) -> F::Output {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    let runtime = RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("test runtime")
+    });
+    runtime.block_on(async {
+        db::init_pool()
+            .await
+            .expect("DATABASE_URL must point at Postgres");
+        test.await
+    })
+}
+
+#[test]
+#[ignore = "needs DATABASE_URL"]
+fn timestamps_round_trip() {
+    run(async {
+        let at = Utc.with_ymd_and_hms(2024, 2, 29, 23, 59, 58).unwrap()
+            + chrono::Duration::microseconds(123_456);
+
+        let row = db::query_one("SELECT $1 AS at", vec![DbParam::Timestamp(at)])
+            .await
+            .unwrap();
+
+        assert_eq!(row.get::<chrono::DateTime<Utc>, _>("at"), at);
+    });
+}
+
+#[test]
+#[ignore = "needs DATABASE_URL"]
+fn json_round_trips() {
+    run(async {
+        let value = json!({ "name": "ada", "tags": ["a", "b"], "nested": { "n": 1.5 } });
+
+        let row = db::query_one("SELECT $1 AS doc", vec![DbParam::Json(value.clone())])
+            .await
+            .unwrap();
+
+        assert_eq!(row.get::<serde_json::Value, _>("doc"), value);
+    });
+}
+
+#[test]
+#[ignore = "needs DATABASE_URL"]
+fn any_matches_the_array_elements() {
+    run(async {
+        let sql = "SELECT id FROM generate_series(1, 10) AS id WHERE id = ANY($1) ORDER BY id";
+
+        let rows = db::query(sql, vec![DbParam::Int32Array(vec![2, 5, 9])])
+            .await
+            .unwrap();
+        let ids: Vec<i32> = rows.iter().map(|row| row.get("id")).collect();
+        assert_eq!(ids, [2, 5, 9]);
+
+        let rows = db::query(sql, vec![DbParam::Int32Array(Vec::new())])
+            .await
+            .unwrap();
+        assert!(rows.is_empty());
+    });
+}
+
+#[test]
+#[ignore = "needs DATABASE_URL"]
+fn decimals_round_trip_exactly() {
+    run(async {
+        let price: Decimal = "12345678901234.5678".parse().unwrap();
+        let tenth: Decimal = "0.1".parse().unwrap();
+        let fifth: Decimal = "0.2".parse().unwrap();
+
+        let row = db::query_one(
+            "SELECT $1 AS price, $2 + $3 AS sum",
+            vec![
+                DbParam::Decimal(price),
+                DbParam::Decimal(tenth),
+                DbParam::Decimal(fifth),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(row.get::<Decimal, _>("price"), price);
+        assert_eq!(
+            row.get::<Decimal, _>("sum"),
+            "0.3".parse::<Decimal>().unwrap()
+        );
+    });
+}
+
+/// Every id `paginate_after` returns, following the cursor until it runs out.
+async fn walk_pages(order: SortOrder) -> Vec<i32> {
+    let mut ids = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = db::paginate_after(
+            "SELECT id FROM generate_series(1, 25) AS id",
+            Vec::new(),
+            "id",
+            cursor,
+            10,
+            order,
+        )
+        .await
+        .unwrap();
+        ids.extend(page.items.iter().map(|row| row.get::<i32, _>("id")));
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return ids,
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs DATABASE_URL"]
+fn keyset_pages_cover_every_row_once() {
+    run(async {
+        assert_eq!(
+            walk_pages(SortOrder::Asc).await,
+            (1..=25).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            walk_pages(SortOrder::Desc).await,
+            (1..=25).rev().collect::<Vec<_>>()
+        );
+    });
+}
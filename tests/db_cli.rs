@@ -0,0 +1,313 @@
+//! Runs the `db_cli` binary against a scratch migrations directory. Tests
+//! that need Postgres are ignored by default; run them with
+//! `DATABASE_URL=... cargo test --test db_cli -- --ignored`. Each one creates
+//! and drops its own database, so they don't see each other's tables.
+
+use sqlx::{Connection, PgConnection};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use uuid::Uuid;
+
+/// A fresh migrations directory, removed when dropped.
+struct ScriptsDir(PathBuf);
+
+impl ScriptsDir {
+    fn new() -> ScriptsDir {
+        let dir = std::env::temp_dir().join(format!("db_cli_{}", Uuid::new_v4().simple()));
+        fs::create_dir_all(dir.join("migrations")).unwrap();
+        ScriptsDir(dir)
+    }
+
+    /// Writes `<id>_<name>_up.sql`, and `_down.sql` when `down` is given.
+    fn migration(&self, id: u64, name: &str, up: &str, down: Option<&str>) {
+        let dir = self.0.join("migrations");
+        fs::write(dir.join(format!("{}_{}_up.sql", id, name)), up).unwrap();
+        if let Some(down) = down {
+            fs::write(dir.join(format!("{}_{}_down.sql", id, name)), down).unwrap();
+        }
+    }
+
+    /// Ids of the `_up.sql` files, in no particular order.
+    fn migration_ids(&self) -> Vec<String> {
+        fs::read_dir(self.0.join("migrations"))
+            .unwrap()
+            .filter_map(|entry| {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                name.ends_with("_up.sql")
+                    .then(|| name.split('_').next().unwrap().to_string())
+            })
+            .collect()
+    }
+}
+
+impl Drop for ScriptsDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// An empty database created for one test.
+struct ScratchDb {
+    name: String,
+    url: String,
+    admin_url: String,
+}
+
+impl ScratchDb {
+    async fn create() -> ScratchDb {
+        let admin_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point at Postgres");
+        let name = format!("db_cli_{}", Uuid::new_v4().simple());
+        let mut admin = PgConnection::connect(&admin_url).await.unwrap();
+        sqlx::query(&format!("CREATE DATABASE {}", name))
+            .execute(&mut admin)
+            .await
+            .unwrap();
+        let _ = admin.close().await;
+
+        // Same server and credentials, different database
+        let (base, query) = match admin_url.split_once('?') {
+            Some((base, query)) => (base, format!("?{}", query)),
+            None => (admin_url.as_str(), String::new()),
+        };
+        let server = base.rsplit_once('/').map_or(base, |(server, _)| server);
+        let url = format!("{}/{}{}", server, name, query);
+        ScratchDb {
+            name,
+            url,
+            admin_url,
+        }
+    }
+
+    async fn connect(&self) -> PgConnection {
+        PgConnection::connect(&self.url).await.unwrap()
+    }
+
+    async fn drop(self) {
+        let mut admin = PgConnection::connect(&self.admin_url).await.unwrap();
+        sqlx::query(&format!(
+            "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+            self.name
+        ))
+        .execute(&mut admin)
+        .await
+        .unwrap();
+        let _ = admin.close().await;
+    }
+}
+
+fn db_cli(dir: &ScriptsDir, db: Option<&ScratchDb>, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_db_cli"));
+    command.args(args).env("MIGRATIONS_DIR", &dir.0);
+    if let Some(db) = db {
+        command.env("DATABASE_URL", &db.url);
+    }
+    command
+}
+
+fn run(mut command: Command) -> Output {
+    let output = command.output().unwrap();
+    eprintln!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+async fn table_exists(conn: &mut PgConnection, table: &str) -> bool {
+    sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(table)
+        .fetch_one(conn)
+        .await
+        .unwrap()
+}
+
+async fn applied_ids(conn: &mut PgConnection) -> Vec<String> {
+    sqlx::query_scalar("SELECT id FROM _migrations ORDER BY id")
+        .fetch_all(conn)
+        .await
+        .unwrap()
+}
+
+#[test]
+fn new_migrations_get_distinct_ids() {
+    let dir = ScriptsDir::new();
+
+    for _ in 0..2 {
+        let output = run(db_cli(&dir, None, &["migration:new", "add_users"]));
+        assert!(output.status.success());
+    }
+
+    let mut ids = dir.migration_ids();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 2);
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn a_failing_migration_leaves_nothing_behind() {
+    let dir = ScriptsDir::new();
+    dir.migration(
+        1,
+        "broken",
+        "CREATE TABLE half_done (id int);\nSELECT 1 / 0;\n",
+        None,
+    );
+    let db = ScratchDb::create().await;
+
+    let output = run(db_cli(&dir, Some(&db), &["migrate"]));
+
+    let mut conn = db.connect().await;
+    assert!(!output.status.success());
+    assert!(!table_exists(&mut conn, "half_done").await);
+    assert!(applied_ids(&mut conn).await.is_empty());
+    let _ = conn.close().await;
+    db.drop().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn a_failing_tracking_insert_rolls_back_the_migration() {
+    let dir = ScriptsDir::new();
+    // The script itself succeeds, but recording it afterwards can't
+    dir.migration(
+        1,
+        "untrackable",
+        "CREATE TABLE never_recorded (id int);\n\
+         ALTER TABLE _migrations ADD CONSTRAINT refuse_rows CHECK (false) NOT VALID;\n",
+        None,
+    );
+    let db = ScratchDb::create().await;
+
+    let output = run(db_cli(&dir, Some(&db), &["migrate"]));
+
+    let mut conn = db.connect().await;
+    assert!(!output.status.success());
+    assert!(!table_exists(&mut conn, "never_recorded").await);
+    assert!(applied_ids(&mut conn).await.is_empty());
+    let _ = conn.close().await;
+    db.drop().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn migrate_to_moves_forward_and_back() {
+    let dir = ScriptsDir::new();
+    for id in 1..=3 {
+        dir.migration(
+            id,
+            &format!("step_{}", id),
+            &format!("CREATE TABLE step_{} (id int);\n", id),
+            Some(&format!("DROP TABLE step_{};\n", id)),
+        );
+    }
+    let db = ScratchDb::create().await;
+    let mut conn = db.connect().await;
+
+    assert!(
+        run(db_cli(&dir, Some(&db), &["migrate:to", "2"]))
+            .status
+            .success()
+    );
+    assert_eq!(applied_ids(&mut conn).await, ["1", "2"]);
+    assert!(table_exists(&mut conn, "step_2").await);
+    assert!(!table_exists(&mut conn, "step_3").await);
+
+    assert!(
+        run(db_cli(&dir, Some(&db), &["migrate:to", "1"]))
+            .status
+            .success()
+    );
+    assert_eq!(applied_ids(&mut conn).await, ["1"]);
+    assert!(table_exists(&mut conn, "step_1").await);
+    assert!(!table_exists(&mut conn, "step_2").await);
+
+    assert!(
+        run(db_cli(&dir, Some(&db), &["migrate:to", "3"]))
+            .status
+            .success()
+    );
+    assert_eq!(applied_ids(&mut conn).await, ["1", "2", "3"]);
+    assert!(table_exists(&mut conn, "step_3").await);
+
+    let _ = conn.close().await;
+    db.drop().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn concurrent_runs_apply_a_migration_once() {
+    let dir = ScriptsDir::new();
+    // Slow enough that the second run starts while the first holds the lock;
+    // without the lock, its CREATE TABLE would fail
+    dir.migration(
+        1,
+        "slow",
+        "SELECT pg_sleep(1);\nCREATE TABLE created_once (id int);\n",
+        None,
+    );
+    let db = ScratchDb::create().await;
+
+    let runs: Vec<_> = (0..2)
+        .map(|_| {
+            db_cli(&dir, Some(&db), &["migrate"])
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    let outputs: Vec<Output> = runs
+        .into_iter()
+        .map(|run| run.wait_with_output().unwrap())
+        .collect();
+
+    assert!(outputs.iter().all(|output| output.status.success()));
+    let applied_by = outputs
+        .iter()
+        .filter(|output| String::from_utf8_lossy(&output.stdout).contains("Applied migration"))
+        .count();
+    assert_eq!(applied_by, 1);
+    let mut conn = db.connect().await;
+    assert_eq!(applied_ids(&mut conn).await, ["1"]);
+    let _ = conn.close().await;
+    db.drop().await;
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn dry_run_reads_an_old_tracking_table_without_changing_it() {
+    let dir = ScriptsDir::new();
+    dir.migration(1, "first", "CREATE TABLE first (id int);\n", None);
+    dir.migration(2, "second", "CREATE TABLE second (id int);\n", None);
+    let db = ScratchDb::create().await;
+    let mut conn = db.connect().await;
+    // Tracking table as created before checksums were recorded
+    sqlx::raw_sql(
+        "CREATE TABLE _migrations (id TEXT PRIMARY KEY, name TEXT NOT NULL, \
+         applied_at TIMESTAMP NOT NULL DEFAULT NOW());\n\
+         INSERT INTO _migrations (id, name) VALUES ('1', 'first');",
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    let output = run(db_cli(&dir, Some(&db), &["migrate", "--dry-run"]));
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2_second_up.sql"));
+    assert!(!stdout.contains("1_first_up.sql"));
+    let upgraded: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM information_schema.columns \
+         WHERE table_name = '_migrations' AND column_name = 'checksum')",
+    )
+    .fetch_one(&mut conn)
+    .await
+    .unwrap();
+    assert!(!upgraded);
+    let _ = conn.close().await;
+    db.drop().await;
+}